@@ -5,6 +5,10 @@ pub struct Env {
     pub database_url: String,
     pub cookie_secret: String,
     pub public_url: String,
+    /// external tokenizers, see `core::lang::tokenizer::external::Plugin`
+    pub tokenizer_plugins: Vec<String>,
+    /// lexer of C/C++ submissions, see `core::lang::tokenizer::cpp::Backend`
+    pub cpp_backend: Option<String>,
 }
 
 fn get_env() -> Env {
@@ -12,6 +16,14 @@ fn get_env() -> Env {
         database_url: var("DATABASE_URL").expect("DATABASE_URL"),
         cookie_secret: var("COOKIE_SECRET").expect("COOKIE_SECRET"),
         public_url: var("PUBLIC_URL").expect("PUBLIC_URL"),
+        // separated by `;`
+        tokenizer_plugins: var("TOKENIZER_PLUGINS")
+            .unwrap_or_default()
+            .split(';')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.trim().to_string())
+            .collect(),
+        cpp_backend: var("CPP_BACKEND").ok(),
     }
 }
 
@@ -1,12 +1,20 @@
+use anyhow::{anyhow, Context};
 use api::{
     def::{LoginRequest, Submission, SubmitRequest},
     env::ENV,
 };
 use clap::Parser;
-use core::lang::Language;
+use core::{
+    decode,
+    lang::{
+        self,
+        detect::{sniff, Override},
+        notebook::Notebook,
+        Language,
+    },
+};
 use dotenv::dotenv;
-use encoding::{DecoderTrap, Encoding};
-use log::{info, warn};
+use log::info;
 use regex::Regex;
 use std::{ffi::OsString, path::Path, path::PathBuf};
 use walkdir::WalkDir;
@@ -33,78 +41,128 @@ struct Args {
     #[arg(short = 'r', long)]
     regex: Option<Regex>,
 
+    /// Languages of files by extension or glob, e.g. `ino=cpp` or `*.txt=python`
+    #[arg(short = 'L', long)]
+    language_override: Vec<Override>,
+
     /// Paths to source code
     code: Vec<PathBuf>,
 }
 
-fn read_file(path: &Path) -> String {
-    let content = std::fs::read(path).unwrap();
-    match String::from_utf8(content.clone()) {
-        Ok(content) => {
-            return content;
-        }
-        Err(err) => {
-            warn!(
-                "Failed to parse utf8 for {}: {}, trying to decoding as GB18030",
+// in any encoding core can decode, as the server tokenizes what is submitted
+fn read_file(path: &Path) -> anyhow::Result<String> {
+    let (content, encoding) =
+        decode::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if encoding != decode::Encoding::Utf8 {
+        info!("Decoded {} as {}", path.display(), encoding);
+    }
+    Ok(content)
+}
+
+// notebooks are flattened into scripts, so that reports show code instead of JSON
+fn read_source(path: &Path) -> anyhow::Result<String> {
+    let content = read_file(path)?;
+    if path.extension() == Some(&OsString::from("ipynb")) {
+        match Notebook::parse(&content) {
+            Ok(notebook) => Ok(notebook.to_script()),
+            Err(err) => Err(anyhow!(
+                "Failed to parse notebook {}: {}",
                 path.display(),
                 err
-            );
-
-            match encoding::all::GB18030.decode(&content, DecoderTrap::Strict) {
-                Ok(content) => {
-                    info!("Succeeded to parse gb18030 for {}: {}", path.display(), err);
-                    return content;
-                }
-                Err(err) => {
-                    panic!("Failed to parse gb18030 for {}: {}", path.display(), err);
-                }
-            }
+            )),
         }
+    } else {
+        Ok(content)
+    }
+}
+
+// files of the language in a directory: by extension, by an override, or by a
+// shebang or modeline if there is no extension
+fn is_source(
+    language: &Language,
+    extensions: &[&str],
+    path: &Path,
+    overrides: &[Override],
+) -> bool {
+    if let Some(o) = overrides.iter().find(|o| o.matches(path)) {
+        return o.language == *language;
+    }
+    match path.extension() {
+        Some(extension) => extensions
+            .iter()
+            .any(|ext| extension.to_ascii_lowercase() == *ext),
+        None => decode::read(path).is_ok_and(|(content, _)| sniff(&content) == Some(*language)),
     }
 }
 
-fn collect(language: &Language, path: &Path, regex: &Option<Regex>) -> String {
+fn collect(
+    language: &Language,
+    path: &Path,
+    regex: &Option<Regex>,
+    overrides: &[Override],
+) -> anyhow::Result<String> {
     let comment = match &language {
         Language::Cpp => "//",
+        Language::C => "//",
         Language::Rust => "//",
         Language::Python => "#",
         Language::Verilog => "//",
-        Language::SQL => "--",
+        Language::SQL(_) => "--",
         Language::JavaScript => "//",
         Language::Lua => "--",
+        Language::Java => "//",
+        Language::Go => "//",
+        Language::TypeScript => "//",
+        Language::Vhdl => "--",
+        Language::SystemVerilog => "//",
+        Language::Assembly => "#",
+        Language::MipsAssembly => "#",
+        Language::Haskell => "--",
+        Language::OCaml => "(*",
+        Language::Notebook => "#",
+        Language::Scala => "//",
+        Language::Chisel => "//",
+        Language::Matlab => "%",
+        Language::Prose => "<!--",
+        // comment syntax unknown, so files go without a header
+        Language::Custom(_) => "",
     };
-    let extensions = match &language {
-        Language::Cpp => ["cpp", "h"].to_vec(),
-        Language::Rust => ["rs"].to_vec(),
-        Language::Python => ["py"].to_vec(),
-        Language::Verilog => ["v"].to_vec(),
-        Language::SQL => ["sql"].to_vec(),
-        Language::JavaScript => ["js"].to_vec(),
-        Language::Lua => ["lua"].to_vec(),
+    // for languages with block comments only
+    let comment_end = match &language {
+        Language::OCaml => " *)",
+        Language::Prose => " -->",
+        _ => "",
     };
+    let mut extensions = lang::extensions(*language);
+    // notebooks are submitted as their Python code, see `read_source`
+    if *language == Language::Python {
+        extensions.push("ipynb");
+    }
 
     if std::path::Path::new(path).is_file() {
         // one file
-        read_file(path)
+        read_source(path)
     } else {
         // find all sources and concat
         let mut source_code = String::new();
         for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-            for ext in &extensions {
-                if entry.path().extension() == Some(&OsString::from(ext)) {
-                    if let Some(regex) = regex {
-                        if !regex.is_match(&format!("{}", entry.path().display())) {
-                            continue;
-                        }
-                    }
-                    source_code += &format!("{} {} \n", comment, entry.path().display());
-                    source_code += &read_file(entry.path());
-                    source_code += "\n";
-                    break;
+            if !entry.file_type().is_file()
+                || !is_source(language, &extensions, entry.path(), overrides)
+            {
+                continue;
+            }
+            if let Some(regex) = regex {
+                if !regex.is_match(&format!("{}", entry.path().display())) {
+                    continue;
                 }
             }
+            if !comment.is_empty() {
+                source_code += &format!("{} {}{} \n", comment, entry.path().display(), comment_end);
+            }
+            source_code += &read_source(entry.path())?;
+            source_code += "\n";
         }
-        source_code
+        Ok(source_code)
     }
 }
 
@@ -113,18 +171,31 @@ fn main() -> anyhow::Result<()> {
     let opts = Args::parse();
     env_logger::init();
 
-    let language = match opts.language.as_str() {
-        "c++" | "cpp" | "cc" => Language::Cpp,
-        "python" | "py" => Language::Python,
-        "rust" => Language::Rust,
-        _ => unimplemented!("Language: {}", opts.language),
+    let language = match opts.language.parse() {
+        Ok(language) => language,
+        Err(_) => {
+            // left to a server that has it registered
+            info!("Submitting {} as a registered language", opts.language);
+            Language::Custom(opts.language.clone().leak())
+        }
     };
 
     let client = reqwest::blocking::Client::new();
     let template = opts
         .template
         .as_ref()
-        .map(|template| collect(&language, template, &opts.regex));
+        .map(|template| collect(&language, template, &opts.regex, &opts.language_override))
+        .transpose()?;
+    let submissions = opts
+        .code
+        .iter()
+        .map(|code| {
+            Ok(Submission {
+                name: format!("{}", code.display()),
+                code: collect(&language, code, &opts.regex, &opts.language_override)?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
     let body = client
         .post(format!("{}/api/submit", ENV.public_url))
         .json(&SubmitRequest {
@@ -134,14 +205,7 @@ fn main() -> anyhow::Result<()> {
             }),
             language,
             template,
-            submissions: opts
-                .code
-                .iter()
-                .map(|code| Submission {
-                    name: format!("{}", code.display()),
-                    code: collect(&language, code, &opts.regex),
-                })
-                .collect::<Vec<_>>(),
+            submissions,
         })
         .send()?
         .text()?;
@@ -1,10 +1,27 @@
 use clap::Parser;
-use core::{common::gen_svg_with_index, lang::tokenize, matching::compute_matches_from_token};
+use core::{
+    common::gen_svg_with_index,
+    decode,
+    lang::{
+        detect::Override,
+        detect_content,
+        notebook::{describe_lines, Notebook},
+        tokenize_content,
+        tokenizer::{
+            cpp::{self, preprocess, Backend, Cpp, Dialect},
+            external::Plugin,
+        },
+        Language, Tokenize,
+    },
+    matching::{compute_matches_from_token, slice_lines, token_range},
+    rkr_gst::Match,
+    token::{Kind, Token, Tokenized},
+};
+use log::warn;
 use random_color::{Luminosity, RandomColor};
-use rkr_gst::Match;
 use std::{
     fs::File,
-    io::{Read, Write},
+    io::Write,
     path::{Path, PathBuf},
 };
 
@@ -27,42 +44,132 @@ struct Args {
 
     #[arg(short = 'M', long, default_value_t = 20)]
     rkr_gst_minimum_match_length: usize,
+
+    /// Lexer of C/C++ sources: `builtin`, or `clang` if built with the `clang`
+    /// feature; preprocessed sources always go through the built-in one
+    #[arg(long, default_value = "builtin")]
+    cpp_backend: Backend,
+
+    /// Expand macros in C/C++ sources, searching includes in these directories
+    #[arg(short = 'I', long)]
+    include_dir: Vec<PathBuf>,
+
+    /// Expand macros in C/C++ sources, with these `NAME` or `NAME=VALUE` definitions
+    #[arg(short = 'D', long)]
+    define: Vec<String>,
+
+    /// Expand macros in C/C++ sources, with flags from this compilation database
+    #[arg(long)]
+    compile_commands: Option<PathBuf>,
+
+    /// Languages of files by extension or glob, e.g. `ino=cpp` or `*.txt=python`
+    #[arg(short = 'L', long)]
+    language_override: Vec<Override>,
+
+    /// Languages tokenized by external commands, as `NAME:EXTENSIONS:COMMAND`,
+    /// e.g. `racket:rkt:racket lex.rkt`
+    #[arg(long)]
+    plugin: Vec<Plugin>,
 }
 
-fn read_file_lines(s: &Path) -> anyhow::Result<Vec<String>> {
-    let mut file = File::open(s)?;
-    let mut s = String::new();
-    file.read_to_string(&mut s)?;
-    Ok(s.lines().map(String::from).collect::<Vec<String>>())
+// tokenize, preprocessing C/C++ sources if asked to, along with the lines
+// that token positions refer to
+fn tokenize_file(path: &Path, opts: &Args) -> anyhow::Result<(Language, Vec<String>, Tokenized)> {
+    // decoded once for both, so that token positions match
+    let (content, encoding) = decode::read(path)?;
+    let language = detect_content(path, &content, &opts.language_override)?;
+    let tokenized = tokenize_decoded(path, &content, language, opts)?;
+    // token lines of notebooks refer to the flattened script
+    let lines = if language == Language::Notebook {
+        Notebook::parse(&content)?.to_script()
+    } else {
+        content
+    };
+    Ok((
+        language,
+        lines.lines().map(String::from).collect(),
+        Tokenized {
+            encoding,
+            ..tokenized
+        },
+    ))
+}
+
+fn tokenize_decoded(
+    path: &Path,
+    content: &str,
+    language: Language,
+    opts: &Args,
+) -> anyhow::Result<Tokenized> {
+    let is_cpp = matches!(language, Language::Cpp | Language::C);
+    let preprocess =
+        !opts.include_dir.is_empty() || !opts.define.is_empty() || opts.compile_commands.is_some();
+    if !is_cpp || !preprocess {
+        return tokenize_content(path, content, language);
+    }
+
+    let mut options = match &opts.compile_commands {
+        Some(database) => preprocess::Options::from_compile_commands(database, path, path.parent())
+            .unwrap_or_else(|err| {
+                warn!("{err}, preprocessing with --include-dir and --define only");
+                preprocess::Options::default()
+            }),
+        None => preprocess::Options::default(),
+    };
+    options
+        .include_dirs
+        .extend(opts.include_dir.iter().cloned());
+    options.defines.extend(opts.define.iter().cloned());
+    Ok(Cpp {
+        dialect: match language {
+            Language::C => Dialect::C,
+            _ => Dialect::Cpp,
+        },
+        preprocess: Some(options),
+        ..Default::default()
+    }
+    .tokenize_content(path, content))
 }
 
 fn main() -> anyhow::Result<()> {
     let opts = Args::parse();
     env_logger::init();
+    cpp::set_backend(opts.cpp_backend);
+    for plugin in &opts.plugin {
+        plugin.clone().register()?;
+    }
 
-    let token_left = core::lang::tokenize(&opts.left).unwrap();
-    let token_kind_left: Vec<u8> = token_left.iter().map(|t| t.kind).collect();
-    let lines_left = read_file_lines(&opts.left)?;
+    let (language_left, lines_left, left) = tokenize_file(&opts.left, &opts)?;
+    let token_left = left.tokens;
+    let token_kind_left: Vec<Kind> = token_left.iter().map(|t| t.kind).collect();
 
-    let token_right: Vec<core::token::Token> = core::lang::tokenize(&opts.right).unwrap();
-    let token_kind_right: Vec<u8> = token_right.iter().map(|t| t.kind).collect();
-    let lines_right = read_file_lines(&opts.right)?;
+    let (language_right, lines_right, right) = tokenize_file(&opts.right, &opts)?;
+    let token_right: Vec<Token> = right.tokens;
+    let token_kind_right: Vec<Kind> = token_right.iter().map(|t| t.kind).collect();
 
-    let template_kind: Option<Vec<u8>> = opts.template.as_ref().map(|t| {
-        let token_template = tokenize(t).unwrap();
-        token_template.iter().map(|t| t.kind).collect()
-    });
+    let template_kind: Option<Vec<Kind>> = match &opts.template {
+        Some(t) => {
+            let (_, _, template) = tokenize_file(t, &opts)?;
+            let token_template = template.tokens;
+            Some(token_template.iter().map(|t| t.kind).collect())
+        }
+        None => None,
+    };
+
+    for (path, diagnostics) in [
+        (&opts.left, &left.diagnostics),
+        (&opts.right, &right.diagnostics),
+    ] {
+        for diagnostic in diagnostics {
+            warn!("{} is only partly parsed: {}", path.display(), diagnostic);
+        }
+    }
 
     let matches = compute_matches_from_token(
         &token_left,
         &token_kind_left,
-        &lines_left.iter().map(|l| l.as_str()).collect::<Vec<&str>>(),
         &token_right,
         &token_kind_right,
-        &lines_right
-            .iter()
-            .map(|l| l.as_str())
-            .collect::<Vec<&str>>(),
         template_kind.as_deref(),
         Some(opts.rkr_gst_initial_search_length),
         Some(opts.rkr_gst_minimum_match_length),
@@ -71,71 +178,103 @@ fn main() -> anyhow::Result<()> {
     for is_left in [true, false] {
         let side = if is_left { "left" } else { "right" };
         let mut file = File::create(format!("match-{}.html", side))?;
-        writeln!(
-            file,
-            "<html><head><meta charset=\"UTF-8\"></head><body><pre>"
-        )?;
+        writeln!(file, "<html><head><meta charset=\"UTF-8\"></head><body>")?;
+        let diagnostics = if is_left {
+            &left.diagnostics
+        } else {
+            &right.diagnostics
+        };
+        if !diagnostics.is_empty() {
+            writeln!(file, "<p>Only partly parsed:</p><ul>")?;
+            for diagnostic in diagnostics {
+                writeln!(
+                    file,
+                    "<li>{}</li>",
+                    html_escape::encode_text(&diagnostic.to_string())
+                )?;
+            }
+            writeln!(file, "</ul>")?;
+        }
+        writeln!(file, "<pre>")?;
 
-        let mut last_line = 0;
+        let mut last = (0, 0);
 
         let mut matches: Vec<(usize, &Match)> = matches.iter().enumerate().collect();
 
-        // sort by line_from
+        // sort by where they start
         matches.sort_by_key(|m| {
-            if is_left {
-                token_left[m.1.pattern_index].line as usize - 1
+            let t = if is_left {
+                &token_left[m.1.pattern_index]
             } else {
-                token_right[m.1.text_index].line as usize - 1
-            }
+                &token_right[m.1.text_index]
+            };
+            (t.line, t.column)
         });
         let token = if is_left { &token_left } else { &token_right };
         let lines = if is_left { &lines_left } else { &lines_right };
+        let language = if is_left {
+            language_left
+        } else {
+            language_right
+        };
         for (idx, m) in matches.iter() {
             let index = if is_left {
                 m.pattern_index
             } else {
                 m.text_index
             };
-            let line_from = token[index].line as usize - 1;
-            let line_to = token[index + m.length - 1].line as usize - 1;
+            let (from, to) = token_range(&token[index..][..m.length]);
+            let (line_from, line_to) = (from.0, to.0);
 
             println!("Match #{}:", idx + 1);
-            println!("L{}-L{}:", line_from, line_to);
-            println!("{}", lines[line_from..=line_to].join("\n"));
-
-            assert!(last_line <= line_from);
-            assert!(line_from <= line_to);
-            if last_line < line_from {
-                writeln!(
-                    file,
-                    "{}",
-                    html_escape::encode_text(&lines[last_line..=(line_from - 1)].join("\n"))
-                )?;
+            // only notebooks have cells, other scripts may have markers too
+            let cells = match language {
+                Language::Notebook => {
+                    describe_lines(&lines.join("\n"), line_from as u32 + 1, line_to as u32 + 1)
+                }
+                _ => None,
+            };
+            match cells {
+                Some(cells) => println!("{}:", cells),
+                None => println!("L{}-L{}:", line_from, line_to),
             }
-            last_line = line_to + 1;
+            println!("{}", slice_lines(lines, from, to));
+
+            assert!(last <= from);
+            assert!(from <= to);
+            write!(
+                file,
+                "{}",
+                html_escape::encode_text(&slice_lines(lines, last, from))
+            )?;
+            last = to;
 
             let color = RandomColor::new()
                 .luminosity(Luminosity::Bright)
                 .seed((idx + 1) as u64)
                 .to_hex();
             write!(file, "<font color=\"{}\">", color)?;
-            writeln!(file, "{}", gen_svg_with_index(color.as_str(), 0, Some(idx + 1)))?;
-            writeln!(
+            write!(
                 file,
                 "{}",
-                html_escape::encode_text(&lines[line_from..=line_to].join("\n"))
+                gen_svg_with_index(color.as_str(), 0, Some(idx + 1))
             )?;
-            write!(file, "</font>")?;
-        }
-
-        if last_line < lines.len() {
-            writeln!(
+            // exactly the matched characters
+            write!(
                 file,
                 "{}",
-                html_escape::encode_text(&lines[last_line..].join("\n"))
+                html_escape::encode_text(&slice_lines(lines, from, to))
             )?;
+            write!(file, "</font>")?;
         }
 
+        // the rest
+        write!(
+            file,
+            "{}",
+            html_escape::encode_text(&slice_lines(lines, last, (lines.len(), 0)))
+        )?;
+
         writeln!(file, "</pre></body></html>")?;
     }
 
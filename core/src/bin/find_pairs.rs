@@ -1,12 +1,27 @@
 use clap::Parser;
 use core::{
     common::{all_fingerprint, fingerprint, Fingerprint},
-    token::Token,
+    decode::{self, Encoding},
+    lang::{
+        detect::Override,
+        detect_content, kind_schema, tokenize_content,
+        tokenizer::{
+            cpp::{self, preprocess, Backend, Cpp, Dialect},
+            external::Plugin,
+        },
+        Language, Tokenize,
+    },
+    token::{Token, Tokenized},
 };
-use indicatif::ProgressIterator;
+use indicatif::ParallelProgressIterator;
 use log::*;
+use rayon::prelude::*;
 use regex::Regex;
-use std::{collections::HashMap, fs::read_dir, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
 use walkdir::WalkDir;
 
 #[derive(Parser)]
@@ -37,11 +52,91 @@ struct Args {
 
     #[arg(short='G', long, default_value_t = 80)]
     winnow_guarantee: usize,
+
+    /// Lexer of C/C++ sources: `builtin`, or `clang` if built with the `clang`
+    /// feature; preprocessed sources always go through the built-in one
+    #[arg(long, default_value = "builtin")]
+    cpp_backend: Backend,
+
+    /// Expand macros in C/C++ sources, searching includes in these directories
+    #[arg(short = 'I', long)]
+    include_dir: Vec<PathBuf>,
+
+    /// Expand macros in C/C++ sources, with these `NAME` or `NAME=VALUE` definitions
+    #[arg(short = 'D', long)]
+    define: Vec<String>,
+
+    /// Expand macros in C/C++ sources, with flags from this compilation database
+    #[arg(long)]
+    compile_commands: Option<PathBuf>,
+
+    /// Languages of files by extension or glob, e.g. `ino=cpp` or `*.txt=python`
+    #[arg(short = 'L', long)]
+    language_override: Vec<Override>,
+
+    /// Languages tokenized by external commands, as `NAME:EXTENSIONS:COMMAND`,
+    /// e.g. `racket:rkt:racket lex.rkt`
+    #[arg(long)]
+    plugin: Vec<Plugin>,
+}
+
+// tokenize, preprocessing C/C++ sources if asked to, with headers under `root`
+// as the student's own
+fn tokenize_file(path: &Path, root: &Path, opts: &Args) -> anyhow::Result<(Language, Tokenized)> {
+    let (content, encoding) = decode::read(path)?;
+    let language = detect_content(path, &content, &opts.language_override)?;
+    let is_cpp = matches!(language, Language::Cpp | Language::C);
+    let preprocess =
+        !opts.include_dir.is_empty() || !opts.define.is_empty() || opts.compile_commands.is_some();
+    if !is_cpp || !preprocess {
+        let tokenized = tokenize_content(path, &content, language)?;
+        return Ok((
+            language,
+            Tokenized {
+                encoding,
+                ..tokenized
+            },
+        ));
+    }
+
+    let mut options = match &opts.compile_commands {
+        Some(database) => preprocess::Options::from_compile_commands(database, path, Some(root))
+            .unwrap_or_else(|err| {
+                warn!("{err}, preprocessing with --include-dir and --define only");
+                preprocess::Options::default()
+            }),
+        None => preprocess::Options::default(),
+    };
+    options
+        .include_dirs
+        .extend(opts.include_dir.iter().cloned());
+    options.defines.extend(opts.define.iter().cloned());
+    options.root = Some(root.to_path_buf());
+    let tokenized = Cpp {
+        dialect: match language {
+            Language::C => Dialect::C,
+            _ => Dialect::Cpp,
+        },
+        preprocess: Some(options),
+        ..Default::default()
+    }
+    .tokenize_content(path, &content);
+    Ok((
+        language,
+        Tokenized {
+            encoding,
+            ..tokenized
+        },
+    ))
 }
 
 fn main() -> anyhow::Result<()> {
     let opts = Args::parse();
     env_logger::init();
+    cpp::set_backend(opts.cpp_backend);
+    for plugin in &opts.plugin {
+        plugin.clone().register()?;
+    }
 
     // walk template directory
     info!("Processing template directory");
@@ -58,9 +153,9 @@ fn main() -> anyhow::Result<()> {
             }
         }
         if include {
-            match core::lang::tokenize(path) {
-                Ok(tokens) => {
-                    template_tokens.insert(relative_path.to_path_buf(), tokens);
+            match tokenize_file(path, &opts.template_directory, &opts) {
+                Ok((_, tokenized)) => {
+                    template_tokens.insert(relative_path.to_path_buf(), tokenized.tokens);
                 }
                 Err(err) => {
                     warn!("Tokenize {} failed with {}", path.display(), err);
@@ -81,14 +176,9 @@ fn main() -> anyhow::Result<()> {
     let references = opts.reference_directory.as_ref().map_or(Vec::new(), |dir| {
         read_dir(&dir).unwrap().collect::<Vec<_>>()
     });
-    // map: file => submission => tokens
-    let mut all_tokens: HashMap<PathBuf, HashMap<PathBuf, Vec<Token>>> = HashMap::new();
-    for submission in submissions
-        .chain(references)
-        .collect::<Vec<_>>()
-        .into_iter()
-        .progress()
-    {
+    // files to tokenize: (submission, relative path, path)
+    let mut files = vec![];
+    for submission in submissions.chain(references) {
         let submission = submission?;
         if !submission.file_type()?.is_dir() {
             continue;
@@ -106,22 +196,54 @@ fn main() -> anyhow::Result<()> {
                 }
             }
             if include {
-                match core::lang::tokenize(path) {
-                    Ok(tokens) => {
-                        all_tokens
-                            .entry(relative_path.to_path_buf())
-                            .or_default()
-                            .insert(submission.path(), tokens);
-                    }
-                    Err(err) => {
-                        warn!("Tokenize {} failed with {}", path.display(), err);
-                    }
+                files.push((submission.path(), relative_path.to_path_buf(), path.to_path_buf()));
+            }
+        }
+    }
+
+    // tokenize on all cores
+    let results: Vec<_> = files
+        .into_par_iter()
+        .progress()
+        .map(|(submission, relative_path, path)| {
+            let tokens = tokenize_file(&path, &submission, &opts);
+            (submission, relative_path, path, tokens)
+        })
+        .collect();
+
+    // map: file => submission => tokens
+    let mut all_tokens: HashMap<PathBuf, HashMap<PathBuf, Vec<Token>>> = HashMap::new();
+    let mut languages = vec![];
+    for (submission, relative_path, path, tokens) in results {
+        match tokens {
+            Ok((language, tokenized)) => {
+                if !languages.contains(&language) {
+                    languages.push(language);
+                }
+                if tokenized.encoding != Encoding::Utf8 {
+                    info!("Decoded {} as {}", path.display(), tokenized.encoding);
                 }
+                // compared anyway, as far as it could be tokenized
+                for diagnostic in &tokenized.diagnostics {
+                    warn!("{} is only partly parsed: {}", path.display(), diagnostic);
+                }
+                all_tokens
+                    .entry(relative_path)
+                    .or_default()
+                    .insert(submission, tokenized.tokens);
+            }
+            Err(err) => {
+                warn!("Tokenize {} failed with {}", path.display(), err);
             }
         }
     }
 
     info!("Tokenized {} files in source directory", all_tokens.len());
+    // the results only hold for these, as token kinds and fingerprints change
+    // between versions
+    for language in languages {
+        info!("Compared with {}", kind_schema(language)?);
+    }
 
     for submission in all_tokens.keys() {
         info!("Processing file {}", submission.display());
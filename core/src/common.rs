@@ -1,9 +1,6 @@
-use crate::token::Token;
-use adler32::RollingAdler32;
-use std::{
-    collections::VecDeque,
-    hash::{Hash, Hasher},
-};
+use crate::rkr_gst;
+use crate::token::{Kind, Token};
+use std::collections::VecDeque;
 
 #[derive(Copy, Clone)]
 pub struct LineMatch {
@@ -15,8 +12,8 @@ pub struct LineMatch {
 
 pub fn find_matches(left: &[Token], right: &[Token]) -> Vec<LineMatch> {
     let mut line_matches = vec![];
-    let left_kind: Vec<u8> = left.iter().map(|t| t.kind).collect();
-    let right_kind: Vec<u8> = right.iter().map(|t| t.kind).collect();
+    let left_kind: Vec<Kind> = left.iter().map(|t| t.kind).collect();
+    let right_kind: Vec<Kind> = right.iter().map(|t| t.kind).collect();
     let mut matches = rkr_gst::run(&left_kind, &right_kind, 40, 20);
     matches.sort_by_key(|m| m.pattern_index);
 
@@ -45,6 +42,57 @@ pub fn find_matches(left: &[Token], right: &[Token]) -> Vec<LineMatch> {
     line_matches
 }
 
+/// Version of `stable_hash` and the fingerprints built on it, to be bumped
+/// whenever either changes
+pub const FINGERPRINT_SCHEMA: u32 = 1;
+
+/// 64-bit FNV-1a, finalized like SplitMix64 so that short inputs are spread
+/// too. Unlike `DefaultHasher`, it is fixed across Rust releases, so saved
+/// hashes stay comparable.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+const ADLER_BASE: u32 = 65521;
+
+/// Adler-32 over kinds instead of bytes, rolling over a window of them
+#[derive(Clone, Copy)]
+pub(crate) struct RollingHash {
+    a: u32,
+    b: u32,
+}
+
+impl RollingHash {
+    pub fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    pub fn hash(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+
+    pub fn update(&mut self, kind: Kind) {
+        self.a = (self.a + kind as u32) % ADLER_BASE;
+        self.b = (self.b + self.a) % ADLER_BASE;
+    }
+
+    /// Remove `kind` from the front of a window of `size` kinds
+    pub fn remove(&mut self, size: usize, kind: Kind) {
+        let base = ADLER_BASE as u64;
+        let kind = kind as u64 % base;
+        let size = size as u64 % base;
+        self.a = ((self.a as u64 + base - kind) % base) as u32;
+        self.b = ((self.b as u64 + base - 1 + (base - size) * kind) % base) as u32;
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Fingerprint {
     pub hash: u64,
@@ -55,12 +103,12 @@ pub struct Fingerprint {
 // void winnow(int w)
 pub fn fingerprint<I>(mut iter: I, noise: usize, guarantee: usize) -> Vec<Fingerprint>
 where
-    I: Iterator<Item = u8>,
+    I: Iterator<Item = Kind>,
 {
     let mut res = vec![];
     // initial rolling `noise`-gram hashes
     let mut items = VecDeque::new();
-    let mut hasher = RollingAdler32::new();
+    let mut hasher = RollingHash::new();
     for _ in 0..noise {
         if let Some(e) = iter.next() {
             items.push_back(e);
@@ -81,9 +129,7 @@ where
     let mut min_hash_index = 0;
     for (window_offset, e) in iter.enumerate() {
         // alder32 is not random enough!
-        let mut h = std::collections::hash_map::DefaultHasher::new();
-        hasher.hash().hash(&mut h);
-        let new_hash = h.finish();
+        let new_hash = stable_hash(&hasher.hash().to_le_bytes());
 
         if new_hash < hashes[min_hash_index] {
             // a new minimum
@@ -124,12 +170,12 @@ where
 
 pub fn all_fingerprint<I>(mut iter: I, noise: usize) -> Vec<Fingerprint>
 where
-    I: Iterator<Item = u8>,
+    I: Iterator<Item = Kind>,
 {
     let mut res = vec![];
     // initial rolling `noise`-gram hashes
     let mut items = VecDeque::new();
-    let mut hasher = RollingAdler32::new();
+    let mut hasher = RollingHash::new();
     for _ in 0..noise {
         if let Some(e) = iter.next() {
             items.push_back(e);
@@ -142,9 +188,7 @@ where
 
     for (window_offset, e) in iter.enumerate() {
         // alder32 is not random enough!
-        let mut h = std::collections::hash_map::DefaultHasher::new();
-        hasher.hash().hash(&mut h);
-        let new_hash = h.finish();
+        let new_hash = stable_hash(&hasher.hash().to_le_bytes());
 
         res.push(Fingerprint {
             hash: new_hash,
@@ -159,16 +203,56 @@ where
     res
 }
 
+/// Words of a shell command line, honoring quotes and backslashes
+pub fn split_command(command: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    res.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        res.push(current);
+    }
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use super::all_fingerprint;
     use super::fingerprint;
+    use super::stable_hash;
+    use crate::token::Kind;
 
     #[test]
     fn test_all_fingerprint() {
         // example taken from paper
         let text = "adorunrunrunadorunrun";
-        let fingerprints = all_fingerprint(text.bytes(), 5);
+        let fingerprints = all_fingerprint(text.bytes().map(Kind::from), 5);
         eprintln!("{:?}", fingerprints);
 
         // adoru @ 0, 12
@@ -186,11 +270,11 @@ mod tests {
     fn test_fingerprint() {
         // example taken from paper
         let text = "adorunrunrunadorunrun";
-        let all_fingerprints = all_fingerprint(text.bytes(), 5);
+        let all_fingerprints = all_fingerprint(text.bytes().map(Kind::from), 5);
         eprintln!("{:?}", all_fingerprints);
 
         // windows size of hashes = 2
-        let fingerprints = fingerprint(text.bytes(), 5, 6);
+        let fingerprints = fingerprint(text.bytes().map(Kind::from), 5, 6);
         eprintln!("{:?}", fingerprints);
 
         // check if subset
@@ -198,6 +282,18 @@ mod tests {
             assert!(all_fingerprints.contains(f), "{:?} not found", f);
         }
     }
+
+    #[test]
+    fn test_stable_hash() {
+        // saved fingerprints depend on these, bump FINGERPRINT_SCHEMA if
+        // they change
+        assert_eq!(stable_hash(b""), 17665956581633026203);
+        assert_eq!(stable_hash(b"jieplag"), 738704208050936857);
+        let fingerprints = all_fingerprint("adorunrunrunadorunrun".bytes().map(Kind::from), 5);
+        assert_eq!(fingerprints[0].hash, 13129597151614023305);
+        // rolled over to `runru`
+        assert_eq!(fingerprints[3].hash, 930534722676073751);
+    }
 }
 
 pub fn gen_svg_with_index(color: &str, ratio: i32, index: Option<usize>) -> String {
@@ -0,0 +1,150 @@
+//! Decoding source files of unknown encoding, as submitted by students on any
+//! platform: UTF-8 or UTF-16 by byte order mark or by the NUL bytes of UTF-16,
+//! then UTF-8, then GB18030, which covers GBK and GB2312, then Latin-1, which
+//! takes any bytes. Line endings are normalized to `\n`, so that lines and
+//! columns agree across platforms.
+use encoding::{all::GB18030, DecoderTrap, Encoding as _};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, path::Path};
+
+/// Encoding a source was decoded from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// also for content given as a string
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Gb18030,
+    Latin1,
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Gb18030 => "GB18030",
+            Encoding::Latin1 => "Latin-1",
+        })
+    }
+}
+
+/// Read and decode a file, failing only if it cannot be read
+pub fn read(path: &Path) -> anyhow::Result<(String, Encoding)> {
+    Ok(decode(&std::fs::read(path)?))
+}
+
+/// Decode in the first encoding that fits, with `\n` line endings and
+/// without a byte order mark
+pub fn decode(bytes: &[u8]) -> (String, Encoding) {
+    let (content, encoding) = if let Some(rest) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        (String::from_utf8_lossy(rest).into_owned(), Encoding::Utf8)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+        (utf16(rest, u16::from_le_bytes), Encoding::Utf16Le)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+        (utf16(rest, u16::from_be_bytes), Encoding::Utf16Be)
+    } else if let Some(encoding) = sniff_utf16(bytes) {
+        let content = match encoding {
+            Encoding::Utf16Le => utf16(bytes, u16::from_le_bytes),
+            _ => utf16(bytes, u16::from_be_bytes),
+        };
+        (content, encoding)
+    } else if let Ok(content) = std::str::from_utf8(bytes) {
+        (content.to_string(), Encoding::Utf8)
+    } else if let Ok(content) = GB18030.decode(bytes, DecoderTrap::Strict) {
+        (content, Encoding::Gb18030)
+    } else {
+        // code points of Latin-1 are its bytes
+        (bytes.iter().map(|b| *b as char).collect(), Encoding::Latin1)
+    };
+    (normalize_newlines(content), encoding)
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|c| unit([c[0], c[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+// UTF-16 without a byte order mark: source code is mostly ASCII, whose high
+// bytes are zero, while other encodings never have NUL in text
+fn sniff_utf16(bytes: &[u8]) -> Option<Encoding> {
+    let sample = &bytes[..bytes.len().min(4096) & !1];
+    let units = sample.len() / 2;
+    if units == 0 {
+        return None;
+    }
+    let zeros = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    if odd * 2 > units && even == 0 {
+        Some(Encoding::Utf16Le)
+    } else if even * 2 > units && odd == 0 {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+// `\r\n` and lone `\r`, of old Mac files, to `\n`
+fn normalize_newlines(content: String) -> String {
+    if !content.contains('\r') {
+        return content;
+    }
+    content.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, Encoding};
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode("int x;\n".as_bytes()),
+            ("int x;\n".to_string(), Encoding::Utf8)
+        );
+        assert_eq!(
+            decode(b"\xef\xbb\xbfx = 1\r\ny = 2\r"),
+            ("x = 1\ny = 2\n".to_string(), Encoding::Utf8)
+        );
+
+        // `// 你好` in GBK
+        assert_eq!(
+            decode(b"// \xc4\xe3\xba\xc3\r\n"),
+            ("// 你好\n".to_string(), Encoding::Gb18030)
+        );
+        // `é` alone is no GB18030
+        assert_eq!(
+            decode(b"// caf\xe9\n"),
+            ("// café\n".to_string(), Encoding::Latin1)
+        );
+    }
+
+    #[test]
+    fn test_utf16() {
+        let le: Vec<u8> = "a = 'é'\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let be: Vec<u8> = "a = 'é'\n"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        assert_eq!(decode(&le), ("a = 'é'\n".to_string(), Encoding::Utf16Le));
+        assert_eq!(decode(&be), ("a = 'é'\n".to_string(), Encoding::Utf16Be));
+
+        let mut bom = vec![0xff, 0xfe];
+        bom.extend("你好".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(decode(&bom), ("你好".to_string(), Encoding::Utf16Le));
+    }
+}
@@ -0,0 +1,369 @@
+//! Which language a file is in, when its extension does not tell: by the
+//! shebang, by an editor modeline, or by telltale code for extensions that
+//! several languages share
+use super::{get_lang_info, tokenizer::sql, Language};
+use anyhow::anyhow;
+use regex::Regex;
+use std::{path::Path, str::FromStr, sync::OnceLock};
+
+/// Language for files matching a pattern, taking precedence over detection
+#[derive(Clone, Debug)]
+pub struct Override {
+    pattern: Regex,
+    pub language: Language,
+}
+
+impl Override {
+    /// `pattern` is either an extension, e.g. `ino`, or a glob, e.g. `*.h` or
+    /// `lib/**/*.txt`, matched against the end of the path
+    pub fn new(pattern: &str, language: Language) -> anyhow::Result<Self> {
+        let regex = if pattern.contains(['*', '?', '/']) {
+            glob_regex(pattern)
+        } else {
+            // extensions are compared case-insensitively, as by `tokenize`
+            format!(r"(?i)\.{}$", regex::escape(pattern.trim_start_matches('.')))
+        };
+        Ok(Self {
+            pattern: Regex::new(&regex)?,
+            language,
+        })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        self.pattern
+            .is_match(&path.to_string_lossy().replace('\\', "/"))
+    }
+}
+
+/// `PATTERN=LANGUAGE`, e.g. `ino=cpp` or `*.txt=python`
+impl FromStr for Override {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (pattern, language) = s
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow!("Expected PATTERN=LANGUAGE, got {:?}", s))?;
+        Self::new(pattern, language.parse()?)
+    }
+}
+
+fn glob_regex(glob: &str) -> String {
+    let mut res = String::from("(?:^|/)");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    // `**/` matches no directory, too
+                    chars.next();
+                    res += "(?:.*/)?";
+                } else {
+                    res += ".*";
+                }
+            }
+            '*' => res += "[^/]*",
+            '?' => res += "[^/]",
+            c => res += &regex::escape(&c.to_string()),
+        }
+    }
+    res + "$"
+}
+
+// plain text may be anything, so it is only taken as prose if nothing else is
+// known
+const PLAIN_TEXT: &str = "txt";
+
+/// Language of a file: by the first matching override, then by extension,
+/// then by a modeline or the shebang
+pub fn detect(path: &Path, content: &str, overrides: &[Override]) -> Option<Language> {
+    if let Some(o) = overrides.iter().find(|o| o.matches(path)) {
+        return Some(o.language);
+    }
+    let extension = extension(path);
+    if extension != PLAIN_TEXT {
+        if let Some(language) = by_extension(&extension) {
+            return Some(disambiguate(language, &extension, content));
+        }
+    }
+    sniff(content).or_else(|| by_extension(&extension))
+}
+
+/// Language declared by the content itself, in a modeline or a shebang
+pub fn sniff(content: &str) -> Option<Language> {
+    modeline(content).or_else(|| shebang(content))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn by_extension(extension: &str) -> Option<Language> {
+    get_lang_info()
+        .into_iter()
+        .find(|lang| lang.extensions.contains(&extension))
+        .map(|lang| lang.name)
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+// dialects that share an extension with the language it maps to
+fn disambiguate(language: Language, extension: &str, content: &str) -> Language {
+    static CPP: OnceLock<Regex> = OnceLock::new();
+    static SYSTEMVERILOG: OnceLock<Regex> = OnceLock::new();
+    static MIPS: OnceLock<Regex> = OnceLock::new();
+    static CHISEL: OnceLock<Regex> = OnceLock::new();
+    let (pattern, dialect) = match language {
+        // headers are C unless they use what C does not have, e.g. `class`
+        // and `new` are identifiers in C
+        Language::C if extension == "h" => (
+            regex(
+                &CPP,
+                r"\bclass\s+\w+\s*[:{;]|\btemplate\s*<|\bnamespace\s+\w|\w::\w|#\s*include\s*<\w+>",
+            ),
+            Language::Cpp,
+        ),
+        Language::Verilog => (
+            regex(
+                &SYSTEMVERILOG,
+                r"\b(always_ff|always_comb|always_latch|logic|interface|typedef|package|class)\b",
+            ),
+            Language::SystemVerilog,
+        ),
+        // RISC-V registers go without `$`
+        Language::Assembly => (
+            regex(
+                &MIPS,
+                r"\$(zero|at|v[01]|a[0-3]|t[0-9]|s[0-8]|k[01]|gp|sp|fp|ra)\b|\bsyscall\b",
+            ),
+            Language::MipsAssembly,
+        ),
+        Language::Scala => (regex(&CHISEL, r"\bimport\s+chisel3\b"), Language::Chisel),
+        _ => return language,
+    };
+    if pattern.is_match(content) {
+        dialect
+    } else {
+        language
+    }
+}
+
+// e.g. `#!/usr/bin/python3` or `#!/usr/bin/env -S node --harmony`
+fn shebang(content: &str) -> Option<Language> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    // versioned, e.g. `python3.11`
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match name {
+        "python" | "pypy" => Some(Language::Python),
+        "node" | "nodejs" => Some(Language::JavaScript),
+        "ts-node" | "tsx" | "deno" | "bun" => Some(Language::TypeScript),
+        "lua" | "luajit" => Some(Language::Lua),
+        "runghc" | "runhaskell" => Some(Language::Haskell),
+        "ocaml" => Some(Language::OCaml),
+        "scala" => Some(Language::Scala),
+        "octave" | "octave-cli" | "matlab" => Some(Language::Matlab),
+        "rust-script" => Some(Language::Rust),
+        "sqlite" => Some(Language::SQL(sql::Dialect::SQLite)),
+        "psql" => Some(Language::SQL(sql::Dialect::PostgreSql)),
+        _ => None,
+    }
+}
+
+// Vim modelines in the first or last five lines, e.g. `# vim: set ft=python:`,
+// or an Emacs one in the first two, e.g. `// -*- mode: c++ -*-`
+fn modeline(content: &str) -> Option<Language> {
+    static VIM: OnceLock<Regex> = OnceLock::new();
+    static EMACS: OnceLock<Regex> = OnceLock::new();
+    let vim = regex(
+        &VIM,
+        r"(?:^|\s)(?:vi|vim|ex):.*?[\s:](?:ft|filetype|syn|syntax)=([\w+-]+)",
+    );
+    let emacs = regex(&EMACS, r"-\*-\s*(.*?)\s*-\*-");
+
+    let lines: Vec<&str> = content.lines().collect();
+    for line in lines.iter().take(2) {
+        if let Some(captures) = emacs.captures(line) {
+            let variables = &captures[1];
+            if !variables.contains(':') {
+                // just the mode, e.g. `-*- python -*-`
+                return mode_language(variables);
+            }
+            let mode = variables
+                .split(';')
+                .filter_map(|variable| variable.split_once(':'))
+                .find(|(name, _)| name.trim() == "mode");
+            if let Some((_, mode)) = mode {
+                return mode_language(mode.trim());
+            }
+        }
+    }
+    let tail = lines.len().saturating_sub(5).max(5);
+    for line in lines.iter().take(5).chain(lines.iter().skip(tail)) {
+        if let Some(captures) = vim.captures(line) {
+            return mode_language(&captures[1]);
+        }
+    }
+    None
+}
+
+// filetypes of Vim and modes of Emacs
+fn mode_language(mode: &str) -> Option<Language> {
+    match mode.to_ascii_lowercase().as_str() {
+        "tuareg" => Some(Language::OCaml),
+        "js" | "js2" => Some(Language::JavaScript),
+        mode => mode.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, sniff, Override};
+    use crate::lang::{extensions, tokenizer::sql, Language};
+    use std::path::Path;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(
+            sniff("#!/usr/bin/env python3\nprint(1)\n"),
+            Some(Language::Python)
+        );
+        assert_eq!(
+            sniff("#!/usr/bin/env -S node --harmony\n"),
+            Some(Language::JavaScript)
+        );
+        assert_eq!(sniff("#!/usr/local/bin/lua5.4\n"), Some(Language::Lua));
+        assert_eq!(sniff("#!/bin/sh\necho hi\n"), None);
+        assert_eq!(
+            sniff("// -*- indent-tabs-mode: nil; mode: c++ -*-\nint x;\n"),
+            Some(Language::Cpp)
+        );
+        assert_eq!(sniff("-- -*- lua -*-\n"), Some(Language::Lua));
+        assert_eq!(
+            sniff("a\nb\nc\nd\ne\nf\ng\n# vim: set ts=4 ft=python:\n"),
+            Some(Language::Python)
+        );
+        assert_eq!(sniff("x = 1\n"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        let path = Path::new;
+        assert_eq!(detect(path("a.py"), "", &[]), Some(Language::Python));
+        assert_eq!(
+            detect(path("run"), "#!/usr/bin/python\n", &[]),
+            Some(Language::Python)
+        );
+        assert_eq!(detect(path("README"), "hello\n", &[]), None);
+
+        // plain text, unless it says otherwise
+        assert_eq!(detect(path("a.txt"), "hello\n", &[]), Some(Language::Prose));
+        assert_eq!(
+            detect(path("a.txt"), "#!/usr/bin/env python\n", &[]),
+            Some(Language::Python)
+        );
+        // shebangs and modelines do not override extensions
+        assert_eq!(
+            detect(path("a.ts"), "#!/usr/bin/env node\n", &[]),
+            Some(Language::TypeScript)
+        );
+        assert_eq!(
+            detect(path("a.c"), "// vim: set ft=cpp:\n", &[]),
+            Some(Language::C)
+        );
+        assert_eq!(
+            detect(path("a.txt"), "# vim: set ft=python:\n", &[]),
+            Some(Language::Python)
+        );
+        assert_eq!(
+            detect(path("build"), "-- -*- lua -*-\n", &[]),
+            Some(Language::Lua)
+        );
+
+        assert_eq!(
+            detect(path("top.v"), "module top(input logic a);\n", &[]),
+            Some(Language::SystemVerilog)
+        );
+        assert_eq!(
+            detect(path("top.v"), "module top(input a);\n", &[]),
+            Some(Language::Verilog)
+        );
+        assert_eq!(
+            detect(path("main.S"), "li $v0, 10\nsyscall\n", &[]),
+            Some(Language::MipsAssembly)
+        );
+        assert_eq!(
+            detect(path("main.s"), "li a7, 10\necall\n", &[]),
+            Some(Language::Assembly)
+        );
+        assert_eq!(
+            detect(path("Top.scala"), "import chisel3._\n", &[]),
+            Some(Language::Chisel)
+        );
+
+        // headers are C, unless they use C++
+        assert_eq!(
+            detect(path("list.h"), "struct node *new;\n", &[]),
+            Some(Language::C)
+        );
+        assert_eq!(
+            detect(path("list.h"), "#include <vector>\n", &[]),
+            Some(Language::Cpp)
+        );
+        assert_eq!(
+            detect(path("list.h"), "template <typename T> struct node;\n", &[]),
+            Some(Language::Cpp)
+        );
+        assert_eq!(
+            detect(path("list.h"), "class List : public Base {};\n", &[]),
+            Some(Language::Cpp)
+        );
+        assert_eq!(
+            detect(path("list.hpp"), "struct node *next;\n", &[]),
+            Some(Language::Cpp)
+        );
+        assert_eq!(
+            detect(path("list.c"), "int a::b;\n", &[]),
+            Some(Language::C)
+        );
+
+        // dialects list the extensions they share, but are never taken by
+        // them alone
+        assert_eq!(extensions(Language::SystemVerilog), ["sv", "svh", "v"]);
+        assert_eq!(
+            extensions(Language::SQL(sql::Dialect::SQLite)),
+            extensions(Language::SQL(sql::Dialect::Generic))
+        );
+        assert_eq!(
+            detect(path("init.sql"), "SELECT 1;\n", &[]),
+            Some(Language::SQL(sql::Dialect::Generic))
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        let overrides: Vec<Override> = ["INO=cpp", "lib/**/*.txt=python", "*.h=c"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let detect = |p: &str| detect(Path::new(p), "", &overrides);
+        assert_eq!(detect("sketch/sketch.ino"), Some(Language::Cpp));
+        assert_eq!(detect("lib/a.txt"), Some(Language::Python));
+        assert_eq!(detect("x/lib/y/z/a.txt"), Some(Language::Python));
+        assert_eq!(detect("doc/a.txt"), Some(Language::Prose));
+        assert_eq!(detect("include/a.h"), Some(Language::C));
+        assert_eq!(detect("include/a.hpp"), Some(Language::Cpp));
+
+        assert!("ino".parse::<Override>().is_err());
+        assert!("ino=cobol".parse::<Override>().is_err());
+    }
+}
@@ -1,94 +1,654 @@
-use crate::token::Token;
+use crate::common::FINGERPRINT_SCHEMA;
+use crate::decode;
+use crate::token::Tokenized;
 use anyhow::anyhow;
-use serde::{Deserialize, Serialize};
-use std::path::Path;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::Display,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+use tokenizer::sql;
 
+pub mod detect;
+pub mod notebook;
 pub mod tokenizer;
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Language {
     Cpp,
+    C,
     Rust,
     Verilog,
     Python,
-    SQL,
+    /// Serialized as `SQL`, `PostgreSQL` or `SQLite`
+    SQL(sql::Dialect),
     JavaScript,
     Lua,
+    Java,
+    Go,
+    TypeScript,
+    Vhdl,
+    SystemVerilog,
+    Assembly,
+    MipsAssembly,
+    Haskell,
+    OCaml,
+    Notebook,
+    Scala,
+    Chisel,
+    Matlab,
+    Prose,
+    /// Registered at runtime, see `register`
+    Custom(&'static str),
+}
+
+impl Language {
+    /// Name when serialized, e.g. in requests to the server
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Cpp => "Cpp",
+            Language::C => "C",
+            Language::Rust => "Rust",
+            Language::Verilog => "Verilog",
+            Language::Python => "Python",
+            Language::SQL(sql::Dialect::Generic) => "SQL",
+            Language::SQL(sql::Dialect::PostgreSql) => "PostgreSQL",
+            Language::SQL(sql::Dialect::SQLite) => "SQLite",
+            Language::JavaScript => "JavaScript",
+            Language::Lua => "Lua",
+            Language::Java => "Java",
+            Language::Go => "Go",
+            Language::TypeScript => "TypeScript",
+            Language::Vhdl => "Vhdl",
+            Language::SystemVerilog => "SystemVerilog",
+            Language::Assembly => "Assembly",
+            Language::MipsAssembly => "MipsAssembly",
+            Language::Haskell => "Haskell",
+            Language::OCaml => "OCaml",
+            Language::Notebook => "Notebook",
+            Language::Scala => "Scala",
+            Language::Chisel => "Chisel",
+            Language::Matlab => "Matlab",
+            Language::Prose => "Prose",
+            Language::Custom(name) => name,
+        }
+    }
+
+    // built-in or registered language of this name
+    fn lookup(name: &str) -> Option<Self> {
+        get_lang_info()
+            .into_iter()
+            .map(|lang| lang.name)
+            .find(|language| language.name() == name)
+    }
+
+    // built-in language by a common name, in any case
+    fn alias(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "c++" | "cpp" | "cc" => Language::Cpp,
+            "c" => Language::C,
+            "python" | "py" => Language::Python,
+            "sql" => Language::SQL(sql::Dialect::Generic),
+            "postgresql" | "postgres" | "pg" => Language::SQL(sql::Dialect::PostgreSql),
+            "sqlite" => Language::SQL(sql::Dialect::SQLite),
+            "rust" => Language::Rust,
+            "verilog" => Language::Verilog,
+            "javascript" | "js" => Language::JavaScript,
+            "lua" => Language::Lua,
+            "java" => Language::Java,
+            "go" | "golang" => Language::Go,
+            "typescript" | "ts" | "tsx" => Language::TypeScript,
+            "vhdl" => Language::Vhdl,
+            "systemverilog" | "sv" => Language::SystemVerilog,
+            "riscv" | "rv" | "asm" => Language::Assembly,
+            "mips" => Language::MipsAssembly,
+            "haskell" | "hs" => Language::Haskell,
+            "ocaml" | "ml" => Language::OCaml,
+            "notebook" | "jupyter" | "ipynb" => Language::Notebook,
+            "scala" => Language::Scala,
+            "chisel" => Language::Chisel,
+            "matlab" | "octave" => Language::Matlab,
+            "prose" | "text" | "markdown" | "md" | "latex" | "tex" => Language::Prose,
+            _ => return None,
+        })
+    }
+}
+
+impl Serialize for Language {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
 }
 
+/// Registered languages are only known once registered in this process
+impl<'de> Deserialize<'de> for Language {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Language::lookup(&name)
+            .ok_or_else(|| de::Error::custom(format!("unsupported language: {}", name)))
+    }
+}
+
+/// Names and common aliases, as given on the command line or in a modeline
+impl FromStr for Language {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Language::alias(s)
+            .or_else(|| Language::lookup(s))
+            .ok_or_else(|| anyhow!("Unsupported language: {}", s))
+    }
+}
+
+/// Tokenizers never give up on malformed code: they return what they could
+/// make of it, with diagnostics for the parts they could not parse
 pub trait Tokenize {
-    fn tokenize(&self, path: &Path) -> anyhow::Result<Vec<Token>> {
-        self.tokenize_str(&std::fs::read_to_string(path)?)
+    /// Fails only if the file cannot be read; it may be in any encoding that
+    /// `decode` detects
+    fn tokenize(&self, path: &Path) -> anyhow::Result<Tokenized> {
+        let (content, encoding) = decode::read(path)?;
+        Ok(Tokenized {
+            encoding,
+            ..self.tokenize_content(path, &content)
+        })
+    }
+    /// `content` is already decoded from `path`, whose extension may tell
+    /// variants apart, e.g. `.ts` from `.tsx`
+    fn tokenize_content(&self, _path: &Path, content: &str) -> Tokenized {
+        self.tokenize_str(content)
+    }
+    fn tokenize_str(&self, content: &str) -> Tokenized;
+}
+
+impl<T: Tokenize + ?Sized> Tokenize for Arc<T> {
+    fn tokenize(&self, path: &Path) -> anyhow::Result<Tokenized> {
+        (**self).tokenize(path)
+    }
+    fn tokenize_content(&self, path: &Path, content: &str) -> Tokenized {
+        (**self).tokenize_content(path, content)
+    }
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        (**self).tokenize_str(content)
+    }
+}
+
+/// How tokens of a language are assigned kinds and fingerprinted, to be
+/// recorded alongside saved kinds or fingerprints: those are only comparable
+/// with newly computed ones if it matches
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KindSchema {
+    pub language: Language,
+    pub kinds: u32,
+    pub fingerprints: u32,
+}
+
+impl KindSchema {
+    /// Fails if `saved` was recorded with different kinds or fingerprints
+    pub fn check(&self, saved: &KindSchema) -> anyhow::Result<()> {
+        if self == saved {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Incompatible token kinds: saved with {}, but now {}",
+                saved,
+                self
+            ))
+        }
+    }
+}
+
+impl Display for KindSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} kinds v{}, fingerprints v{}",
+            self.language.name(),
+            self.kinds,
+            self.fingerprints
+        )
     }
-    fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>>;
 }
 
 struct LangInfo {
     name: Language,
+    /// version of the kinds the tokenizer gives, to be bumped whenever they
+    /// change, including by upgrading a grammar or lexer dependency
+    kind_schema: u32,
     extensions: Vec<&'static str>,
     tokenizer: Box<dyn Tokenize>,
 }
 
+// languages registered at runtime
+struct Registered {
+    name: &'static str,
+    kind_schema: u32,
+    extensions: Vec<&'static str>,
+    tokenizer: Arc<dyn Tokenize + Send + Sync>,
+}
+
+static REGISTRY: RwLock<Vec<Registered>> = RwLock::new(Vec::new());
+
+/// Register a language tokenized outside this crate, e.g. an in-house DSL,
+/// to be accepted wherever built-in ones are: by `name` in `tokenize_str`,
+/// `FromStr` and serde, and by `extensions` (lowercase, without the dot) in
+/// `tokenize`, taking precedence over built-in languages. `kind_schema`
+/// versions the kinds its tokens are given, see `KindSchema`. Fails if the
+/// name is taken.
+pub fn register(
+    name: &'static str,
+    kind_schema: u32,
+    extensions: &[&'static str],
+    tokenizer: impl Tokenize + Send + Sync + 'static,
+) -> anyhow::Result<Language> {
+    // checked and pushed under one lock, so that two threads cannot both
+    // take a name
+    let mut registry = REGISTRY.write().unwrap();
+    let taken = Language::alias(name).is_some()
+        || builtin_lang_info()
+            .iter()
+            .any(|lang| lang.name.name() == name)
+        || registry.iter().any(|lang| lang.name == name);
+    if taken {
+        return Err(anyhow!("Language already exists: {}", name));
+    }
+    registry.push(Registered {
+        name,
+        kind_schema,
+        extensions: extensions.to_vec(),
+        tokenizer: Arc::new(tokenizer),
+    });
+    Ok(Language::Custom(name))
+}
+
 fn get_lang_info() -> Vec<LangInfo> {
+    // registered first, so that they can take over extensions
+    let mut res: Vec<LangInfo> = REGISTRY
+        .read()
+        .unwrap()
+        .iter()
+        .map(|lang| LangInfo {
+            name: Language::Custom(lang.name),
+            kind_schema: lang.kind_schema,
+            extensions: lang.extensions.clone(),
+            tokenizer: Box::new(lang.tokenizer.clone()),
+        })
+        .collect();
+    res.extend(builtin_lang_info());
+    res
+}
+
+fn builtin_lang_info() -> Vec<LangInfo> {
     vec![
+        LangInfo {
+            name: Language::C,
+            kind_schema: 2,
+            // before C++, so that headers are taken as C unless they use C++,
+            // see `detect::disambiguate`
+            extensions: vec!["c", "h"],
+            tokenizer: Box::new(tokenizer::cpp::Cpp {
+                backend: tokenizer::cpp::backend(),
+                dialect: tokenizer::cpp::Dialect::C,
+                ..Default::default()
+            }),
+        },
         LangInfo {
             name: Language::Cpp,
-            extensions: vec!["cpp", "cc", "cxx", "c++", "c", "cu"],
-            tokenizer: Box::new(tokenizer::cpp::Cpp),
+            kind_schema: 2,
+            // `.ino` for Arduino sketches
+            extensions: vec![
+                "cpp", "cc", "cxx", "c++", "cu", "h", "hpp", "hh", "hxx", "ino",
+            ],
+            tokenizer: Box::new(tokenizer::cpp::Cpp {
+                backend: tokenizer::cpp::backend(),
+                ..Default::default()
+            }),
         },
         LangInfo {
             name: Language::Rust,
+            kind_schema: 3,
             extensions: vec!["rs"],
             tokenizer: Box::new(tokenizer::rust::Rust),
         },
         LangInfo {
             name: Language::Verilog,
+            kind_schema: 2,
             extensions: vec!["v"],
             tokenizer: Box::new(tokenizer::verilog::Verilog),
         },
         LangInfo {
             name: Language::Python,
+            kind_schema: 3,
             extensions: vec!["py"],
             tokenizer: Box::new(tokenizer::python::Python),
         },
         LangInfo {
-            name: Language::SQL,
+            name: Language::SQL(sql::Dialect::Generic),
+            kind_schema: 3,
             extensions: vec!["sql"],
-            tokenizer: Box::new(tokenizer::sql::SQL),
+            tokenizer: Box::new(sql::SQL::default()),
+        },
+        LangInfo {
+            name: Language::SQL(sql::Dialect::PostgreSql),
+            kind_schema: 2,
+            // after generic SQL, which `.sql` files are taken as, so this
+            // is only selected explicitly
+            extensions: vec!["sql"],
+            tokenizer: Box::new(sql::SQL {
+                dialect: sql::Dialect::PostgreSql,
+            }),
+        },
+        LangInfo {
+            name: Language::SQL(sql::Dialect::SQLite),
+            kind_schema: 2,
+            // after generic SQL, only selected explicitly
+            extensions: vec!["sql"],
+            tokenizer: Box::new(sql::SQL {
+                dialect: sql::Dialect::SQLite,
+            }),
         },
         LangInfo {
             name: Language::JavaScript,
+            kind_schema: 3,
             extensions: vec!["js"],
             tokenizer: Box::new(tokenizer::javascript::JavaScript),
         },
         LangInfo {
             name: Language::Lua,
+            kind_schema: 3,
             extensions: vec!["lua"],
             tokenizer: Box::new(tokenizer::lua::Lua),
         },
+        LangInfo {
+            name: Language::Java,
+            kind_schema: 3,
+            extensions: vec!["java"],
+            tokenizer: Box::new(tokenizer::java::Java),
+        },
+        LangInfo {
+            name: Language::Go,
+            kind_schema: 3,
+            extensions: vec!["go"],
+            tokenizer: Box::new(tokenizer::go::Go),
+        },
+        LangInfo {
+            name: Language::TypeScript,
+            kind_schema: 3,
+            // boa cannot lex JSX, so it goes through the TSX grammar
+            extensions: vec!["ts", "tsx", "mts", "cts", "jsx"],
+            tokenizer: Box::new(tokenizer::typescript::TypeScript::default()),
+        },
+        LangInfo {
+            name: Language::Vhdl,
+            kind_schema: 2,
+            extensions: vec!["vhd", "vhdl"],
+            tokenizer: Box::new(tokenizer::vhdl::Vhdl),
+        },
+        LangInfo {
+            name: Language::SystemVerilog,
+            kind_schema: 3,
+            // `.v` after Verilog, see `detect::disambiguate`
+            extensions: vec!["sv", "svh", "v"],
+            tokenizer: Box::new(tokenizer::systemverilog::SystemVerilog),
+        },
+        LangInfo {
+            name: Language::Assembly,
+            kind_schema: 2,
+            // extensions are lowercased, so this covers `.S` as well
+            extensions: vec!["s", "asm"],
+            tokenizer: Box::new(tokenizer::assembly::Assembly::default()),
+        },
+        LangInfo {
+            name: Language::MipsAssembly,
+            kind_schema: 2,
+            // after RISC-V, see `detect::disambiguate`
+            extensions: vec!["s", "asm"],
+            tokenizer: Box::new(tokenizer::assembly::Assembly {
+                isa: tokenizer::assembly::Isa::Mips,
+            }),
+        },
+        LangInfo {
+            name: Language::Haskell,
+            kind_schema: 2,
+            extensions: vec!["hs"],
+            tokenizer: Box::new(tokenizer::haskell::Haskell),
+        },
+        LangInfo {
+            name: Language::OCaml,
+            kind_schema: 3,
+            extensions: vec!["ml", "mli"],
+            tokenizer: Box::new(tokenizer::ocaml::OCaml),
+        },
+        LangInfo {
+            name: Language::Notebook,
+            kind_schema: 4,
+            extensions: vec!["ipynb"],
+            tokenizer: Box::new(notebook::Jupyter::default()),
+        },
+        LangInfo {
+            name: Language::Scala,
+            kind_schema: 3,
+            extensions: vec!["scala"],
+            tokenizer: Box::new(tokenizer::scala::Scala::default()),
+        },
+        LangInfo {
+            name: Language::Chisel,
+            kind_schema: 3,
+            // after Scala, see `detect::disambiguate`
+            extensions: vec!["scala"],
+            tokenizer: Box::new(tokenizer::scala::Scala { chisel: true }),
+        },
+        LangInfo {
+            name: Language::Matlab,
+            kind_schema: 2,
+            extensions: vec!["m"],
+            tokenizer: Box::new(tokenizer::matlab::Matlab),
+        },
+        LangInfo {
+            name: Language::Prose,
+            kind_schema: 4,
+            extensions: vec!["md", "markdown", "tex", "txt"],
+            tokenizer: Box::new(tokenizer::prose::Prose { stem: true }),
+        },
     ]
 }
 
-pub fn tokenize(path: &Path) -> anyhow::Result<Vec<Token>> {
-    let extension = path
-        .extension()
-        .and_then(|s| s.to_str())
+pub fn tokenize(path: &Path) -> anyhow::Result<Tokenized> {
+    tokenize_with_overrides(path, &[])
+}
+
+/// Tokenize in the detected language, see `detect::detect`
+pub fn tokenize_with_overrides(
+    path: &Path,
+    overrides: &[detect::Override],
+) -> anyhow::Result<Tokenized> {
+    tokenize_file(path, overrides).map(|(_, tokenized)| tokenized)
+}
+
+/// Tokenize in the detected language, decoding the file just once for both
+pub fn tokenize_file(
+    path: &Path,
+    overrides: &[detect::Override],
+) -> anyhow::Result<(Language, Tokenized)> {
+    let (content, encoding) = decode::read(path)?;
+    let language = detect_content(path, &content, overrides)?;
+    let tokenized = tokenize_content(path, &content, language)?;
+    Ok((
+        language,
+        Tokenized {
+            encoding,
+            ..tokenized
+        },
+    ))
+}
+
+/// Language of a file, see `detect::detect`
+pub fn detect_language(path: &Path, overrides: &[detect::Override]) -> anyhow::Result<Language> {
+    let (content, _) = decode::read(path)?;
+    detect_content(path, &content, overrides)
+}
+
+/// Language of the decoded content of a file, see `detect::detect`
+pub fn detect_content(
+    path: &Path,
+    content: &str,
+    overrides: &[detect::Override],
+) -> anyhow::Result<Language> {
+    detect::detect(path, content, overrides)
+        .ok_or_else(|| anyhow!("Unsupported file extension: {:?}", path))
+}
+
+/// Extensions of files in a language, lowercase and without the dot,
+/// including those shared with other languages, e.g. `h` of both C and C++;
+/// `detect::detect` tells these apart
+pub fn extensions(language: Language) -> Vec<&'static str> {
+    get_lang_info()
+        .into_iter()
+        .find(|lang| lang.name == language)
+        .map(|lang| lang.extensions)
         .unwrap_or_default()
-        .to_ascii_lowercase();
+}
 
+pub fn tokenize_as(path: &Path, language: Language) -> anyhow::Result<Tokenized> {
     for lang in get_lang_info() {
-        if lang.extensions.contains(&extension.as_str()) {
+        if lang.name == language {
             return lang.tokenizer.tokenize(path);
         }
     }
-    Err(anyhow!("Unsupported file extension: {:?}", path))
+    Err(anyhow!("Unsupported language: {:?}", language))
+}
+
+/// Tokenize the decoded content of a file, see `Tokenize::tokenize_content`
+pub fn tokenize_content(
+    path: &Path,
+    content: &str,
+    language: Language,
+) -> anyhow::Result<Tokenized> {
+    for lang in get_lang_info() {
+        if lang.name == language {
+            return Ok(lang.tokenizer.tokenize_content(path, content));
+        }
+    }
+    Err(anyhow!("Unsupported language: {:?}", language))
 }
 
-pub fn tokenize_str(content: &str, language: Language) -> anyhow::Result<Vec<Token>> {
+pub fn tokenize_str(content: &str, language: Language) -> anyhow::Result<Tokenized> {
     for lang in get_lang_info() {
         if lang.name == language {
-            return lang.tokenizer.tokenize_str(content);
+            return Ok(lang.tokenizer.tokenize_str(content));
         }
     }
     Err(anyhow!("Unsupported language: {:?}", language))
 }
+
+pub fn kind_schema(language: Language) -> anyhow::Result<KindSchema> {
+    for lang in get_lang_info() {
+        if lang.name == language {
+            return Ok(KindSchema {
+                language,
+                kinds: lang.kind_schema,
+                fingerprints: FINGERPRINT_SCHEMA,
+            });
+        }
+    }
+    Err(anyhow!("Unsupported language: {:?}", language))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        kind_schema, register, sql, tokenize_as, tokenize_file, tokenize_str, Language, Tokenize,
+    };
+    use crate::token::{Kind, Token, Tokenized};
+
+    // one token per word
+    struct Words;
+
+    impl Tokenize for Words {
+        fn tokenize_str(&self, content: &str) -> Tokenized {
+            content
+                .split_whitespace()
+                .map(|word| Token {
+                    kind: word.len() as Kind,
+                    spelling: word.to_string(),
+                    line: 1,
+                    column: 1,
+                    end_line: 1,
+                    end_column: 1,
+                    bytes: 0..0,
+                })
+                .collect::<Vec<_>>()
+                .into()
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let language = register("words", 3, &["words"], Words).unwrap();
+        assert_eq!(language, Language::Custom("words"));
+        assert!(register("words", 3, &[], Words).is_err());
+        assert!(register("Cpp", 1, &[], Words).is_err());
+
+        let tokens = tokenize_str("a bb ccc", language).unwrap().tokens;
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[2].kind, 3);
+        assert_eq!(kind_schema(language).unwrap().kinds, 3);
+
+        // accepted by name, like built-in languages
+        assert_eq!("words".parse::<Language>().unwrap(), language);
+        assert_eq!(serde_json::to_string(&language).unwrap(), "\"words\"");
+        assert_eq!(
+            serde_json::from_str::<Language>("\"words\"").unwrap(),
+            language
+        );
+        assert_eq!(serde_json::to_string(&Language::Cpp).unwrap(), "\"Cpp\"");
+        assert_eq!(
+            serde_json::from_str::<Language>("\"MipsAssembly\"").unwrap(),
+            Language::MipsAssembly
+        );
+        assert!(serde_json::from_str::<Language>("\"Cobol\"").is_err());
+
+        // dialects keep their names
+        let sqlite = Language::SQL(sql::Dialect::SQLite);
+        assert_eq!(serde_json::to_string(&sqlite).unwrap(), "\"SQLite\"");
+        assert_eq!(
+            serde_json::from_str::<Language>("\"PostgreSQL\"").unwrap(),
+            Language::SQL(sql::Dialect::PostgreSql)
+        );
+        assert_eq!("sqlite".parse::<Language>().unwrap(), sqlite);
+    }
+
+    #[test]
+    fn test_tokenize_file() {
+        // a type assertion, which is JSX in `.tsx`
+        let path = std::env::temp_dir().join(format!("jieplag-{}.ts", std::process::id()));
+        std::fs::write(&path, "let x = <number>y;").unwrap();
+        let (language, tokenized) = tokenize_file(&path, &[]).unwrap();
+        let expected = tokenize_as(&path, Language::TypeScript).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(language, Language::TypeScript);
+        assert!(tokenized.diagnostics.is_empty());
+        let kinds =
+            |tokenized: &Tokenized| tokenized.tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(kinds(&tokenized), kinds(&expected));
+        assert_eq!(tokenized.encoding, expected.encoding);
+    }
+
+    #[test]
+    fn test_register_race() {
+        // only one of the threads registering a name gets it
+        let registered = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| register("race", 1, &[], Words).is_ok()))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .filter(|ok| *ok)
+                .count()
+        });
+        assert_eq!(registered, 1);
+    }
+}
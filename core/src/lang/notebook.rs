@@ -0,0 +1,322 @@
+//! Jupyter notebooks, flattened into Python scripts with one marker line per
+//! cell, so that token lines can be mapped back to cells
+use super::tokenizer::{fallback, python, scanner::LineIndex};
+use super::Tokenize;
+use crate::common::stable_hash;
+use crate::token::{Kind, Token, Tokenized};
+use anyhow::anyhow;
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellKind {
+    Code,
+    Markdown,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cell {
+    pub kind: CellKind,
+    pub source: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Notebook {
+    // raw cells are dropped
+    pub cells: Vec<Cell>,
+}
+
+/// Location of a script line in the notebook, both 1-based
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellLine {
+    pub cell: usize,
+    pub line: u32,
+}
+
+const MARKER: &str = "# %%";
+const MARKDOWN_MARKER: &str = "# %% [markdown]";
+// around the cells, so that lines of other files joined before or after, or
+// scripts with cell markers of their own, are not taken as cells
+const BEGIN_MARKER: &str = "# %% notebook";
+const END_MARKER: &str = "# %% end";
+
+// cell source is either a string or a list of lines
+fn source_of(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(lines) => lines.iter().filter_map(|l| l.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+impl Notebook {
+    /// Parse nbformat 4 or 3 JSON
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let json: Value = serde_json::from_str(content)?;
+        let cells: Vec<&Value> = if let Some(cells) = json.get("cells").and_then(|c| c.as_array()) {
+            cells.iter().collect()
+        } else if let Some(worksheets) = json.get("worksheets").and_then(|w| w.as_array()) {
+            // nbformat 3
+            worksheets
+                .iter()
+                .filter_map(|w| w.get("cells").and_then(|c| c.as_array()))
+                .flatten()
+                .collect()
+        } else {
+            return Err(anyhow!("Not a Jupyter notebook"));
+        };
+
+        let mut res = vec![];
+        for cell in cells {
+            let (kind, source) = match cell.get("cell_type").and_then(|t| t.as_str()) {
+                Some("code") => (
+                    CellKind::Code,
+                    // nbformat 3 calls it input
+                    cell.get("source").or_else(|| cell.get("input")),
+                ),
+                Some("markdown") => (CellKind::Markdown, cell.get("source")),
+                _ => continue,
+            };
+            res.push(Cell {
+                kind,
+                source: source.map(source_of).unwrap_or_default(),
+            });
+        }
+        Ok(Notebook { cells: res })
+    }
+
+    /// Flatten into a Python script, with markdown cells, IPython magics
+    /// and shell escapes commented out
+    pub fn to_script(&self) -> String {
+        let mut res = format!("{}\n", BEGIN_MARKER);
+        for (index, cell) in self.cells.iter().enumerate() {
+            let is_markdown = cell.kind == CellKind::Markdown;
+            let marker = if is_markdown { MARKDOWN_MARKER } else { MARKER };
+            res += &format!("{} cell {}\n", marker, index + 1);
+            // cell magic such as %%bash applies to the whole cell
+            let is_cell_magic = cell.source.starts_with("%%");
+            for line in cell.source.lines() {
+                let trimmed = line.trim_start();
+                if is_markdown
+                    || is_cell_magic
+                    || trimmed.starts_with('%')
+                    || trimmed.starts_with('!')
+                {
+                    res += "# ";
+                }
+                res += line;
+                res += "\n";
+            }
+        }
+        res += END_MARKER;
+        res += "\n";
+        res
+    }
+}
+
+// cell number and whether it is markdown, if the line is a cell marker
+fn parse_marker(line: &str) -> Option<(usize, bool)> {
+    if line == BEGIN_MARKER || line == END_MARKER {
+        return None;
+    }
+    let rest = line.strip_prefix(MARKER)?;
+    let is_markdown = line.starts_with(MARKDOWN_MARKER);
+    let number = rest.rsplit("cell ").next()?.trim().parse().ok()?;
+    Some((number, is_markdown))
+}
+
+/// Map a 1-based line of a flattened notebook back to its cell, or `None`
+/// if the line is a marker or outside of the notebook, e.g. in another file
+/// joined to it
+pub fn locate(script: &str, line: u32) -> Option<CellLine> {
+    let mut in_notebook = false;
+    let mut current = None;
+    for (index, text) in script.lines().enumerate().take(line as usize) {
+        if text == BEGIN_MARKER || text == END_MARKER {
+            in_notebook = text == BEGIN_MARKER;
+            current = None;
+        } else if let Some((cell, _)) = parse_marker(text).filter(|_| in_notebook) {
+            current = Some((cell, index as u32 + 1));
+        }
+    }
+    let (cell, marker_line) = current?;
+    if marker_line == line {
+        return None;
+    }
+    Some(CellLine {
+        cell,
+        line: line - marker_line,
+    })
+}
+
+/// Describe a 1-based line range of a flattened notebook,
+/// e.g. "cell 4, lines 3-10"
+pub fn describe_lines(script: &str, from: u32, to: u32) -> Option<String> {
+    let from = locate(script, from)?;
+    let to = locate(script, to)?;
+    if from.cell != to.cell {
+        Some(format!(
+            "cell {}, line {} - cell {}, line {}",
+            from.cell, from.line, to.cell, to.line
+        ))
+    } else if from.line != to.line {
+        Some(format!(
+            "cell {}, lines {}-{}",
+            from.cell, from.line, to.line
+        ))
+    } else {
+        Some(format!("cell {}, line {}", from.cell, from.line))
+    }
+}
+
+// word tokens of markdown cells in a flattened notebook
+fn tokenize_markdown(script: &str) -> Vec<Token> {
+    // words are hashed into the kinds left unused by python, below those of
+    // fallback tokens
+    let base = python::GRAMMAR.kind_count() as u64;
+    let lines = LineIndex::new(script);
+    let mut res = vec![];
+    let mut in_markdown = false;
+    for (index, line) in script.lines().enumerate() {
+        if line == END_MARKER {
+            in_markdown = false;
+            continue;
+        }
+        if let Some((_, is_markdown)) = parse_marker(line) {
+            in_markdown = is_markdown;
+            continue;
+        }
+        if !in_markdown {
+            continue;
+        }
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if !chars[i].is_alphanumeric() {
+                i += 1;
+                continue;
+            }
+            let mut j = i;
+            while j < chars.len() && chars[j].is_alphanumeric() {
+                j += 1;
+            }
+            let word: String = chars[i..j].iter().collect();
+            let hash = stable_hash(word.to_lowercase().as_bytes());
+            let line = index as u32 + 1;
+            res.push(lines.token(
+                (base + hash % (fallback::FIRST_KIND as u64 - base)) as Kind,
+                word,
+                lines.byte(line, i as u32 + 1)..lines.byte(line, j as u32 + 1),
+            ));
+            i = j;
+        }
+    }
+    res
+}
+
+/// Tokenize code cells with the Python tokenizer, accepting either notebook
+/// JSON or a notebook already flattened by `Notebook::to_script`
+#[derive(Default)]
+pub struct Jupyter {
+    pub include_markdown: bool,
+}
+
+impl Tokenize for Jupyter {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        let mut diagnostics = vec![];
+        let script = if content.trim_start().starts_with('{') {
+            match Notebook::parse(content) {
+                Ok(notebook) => notebook.to_script(),
+                Err(err) => {
+                    // e.g. truncated JSON, taken as a script
+                    diagnostics.push(fallback::diagnostic(err));
+                    content.to_string()
+                }
+            }
+        } else {
+            content.to_string()
+        };
+        let mut res = python::GRAMMAR.tokenize_str(&script);
+        res.diagnostics.splice(0..0, diagnostics);
+        if self.include_markdown {
+            res.tokens.extend(tokenize_markdown(&script));
+            res.tokens.sort_by_key(|t| (t.line, t.column));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_lines, locate, CellLine, Jupyter, Notebook};
+    use crate::lang::Tokenize;
+
+    const NOTEBOOK: &str = r##"{
+ "cells": [
+  {"cell_type": "markdown", "metadata": {}, "source": ["# Title\n", "Some text"]},
+  {"cell_type": "code", "metadata": {}, "outputs": [], "source": ["%matplotlib inline\n", "import numpy as np"]},
+  {"cell_type": "code", "metadata": {}, "outputs": [], "source": "x = np.zeros(3)\nprint(x)"}
+ ],
+ "metadata": {},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = Jupyter::default().tokenize_str(NOTEBOOK).tokens;
+        let script = Notebook::parse(NOTEBOOK).unwrap().to_script();
+
+        eprintln!("{}", script);
+        eprintln!("{:?}", tokens);
+
+        // markdown and magics are skipped
+        assert_eq!(tokens[0].spelling, "import");
+        assert_eq!(
+            locate(&script, tokens[0].line),
+            Some(CellLine { cell: 2, line: 2 })
+        );
+
+        assert_eq!(tokens[4].spelling, "x");
+        assert_eq!(tokens[4].column, 1);
+        assert_eq!(
+            locate(&script, tokens[4].line),
+            Some(CellLine { cell: 3, line: 1 })
+        );
+
+        let last = tokens.last().unwrap();
+        assert_eq!(
+            describe_lines(&script, tokens[4].line, last.line),
+            Some("cell 3, lines 1-2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let tokens = Jupyter {
+            include_markdown: true,
+        }
+        .tokenize_str(NOTEBOOK)
+        .tokens;
+        let script = Notebook::parse(NOTEBOOK).unwrap().to_script();
+
+        assert_eq!(tokens[0].spelling, "Title");
+        assert_eq!(
+            locate(&script, tokens[0].line),
+            Some(CellLine { cell: 1, line: 1 })
+        );
+        assert_eq!(tokens[3].spelling, "import");
+    }
+
+    #[test]
+    fn test_not_notebook() {
+        assert_eq!(locate("x = 1\ny = 2\n", 2), None);
+        // cell markers of a plain script
+        assert_eq!(locate("# %% cell 3\nx = 1\n", 2), None);
+
+        // a file joined after a notebook
+        let script = Notebook::parse(NOTEBOOK).unwrap().to_script() + "# b.py \nx = 1\n";
+        let last = script.lines().count() as u32;
+        assert_eq!(locate(&script, last), None);
+        assert_eq!(describe_lines(&script, last - 1, last), None);
+    }
+}
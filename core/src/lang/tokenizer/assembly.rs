@@ -0,0 +1,583 @@
+use super::{
+    fallback::{recover, Syntax},
+    scanner::Position,
+};
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+
+/// Instruction set, which selects the mnemonics and register names
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Isa {
+    #[default]
+    RiscV,
+    Mips,
+}
+
+#[derive(Default)]
+pub struct Assembly {
+    pub isa: Isa,
+}
+
+impl Tokenize for Assembly {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, |content| tokenize_str(content, self.isa))
+    }
+}
+
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["#", "//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"'],
+};
+
+/// Registers are tokenized by their role in the calling convention,
+/// so that e.g. `t0` and `t1` are not distinguished
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RegisterClass {
+    Zero,
+    ReturnAddress,
+    StackPointer,
+    GlobalPointer,
+    ThreadPointer,
+    AssemblerTemporary,
+    Kernel,
+    ReturnValue,
+    Argument,
+    Temporary,
+    Saved,
+    FloatArgument,
+    FloatTemporary,
+    FloatSaved,
+}
+
+// RV32/64 IMAFD and Zicsr, with the width and ordering suffixes
+// (e.g. `.w`, `.d`, `.aq`) stripped
+const RISCV_MNEMONICS: [&str; 151] = [
+    "lui",
+    "auipc",
+    "jal",
+    "jalr",
+    "beq",
+    "bne",
+    "blt",
+    "bge",
+    "bltu",
+    "bgeu",
+    "lb",
+    "lh",
+    "lw",
+    "lbu",
+    "lhu",
+    "sb",
+    "sh",
+    "sw",
+    "addi",
+    "slti",
+    "sltiu",
+    "xori",
+    "ori",
+    "andi",
+    "slli",
+    "srli",
+    "srai",
+    "add",
+    "sub",
+    "sll",
+    "slt",
+    "sltu",
+    "xor",
+    "srl",
+    "sra",
+    "or",
+    "and",
+    "fence",
+    "ecall",
+    "ebreak",
+    "ld",
+    "sd",
+    "lwu",
+    "addiw",
+    "slliw",
+    "srliw",
+    "sraiw",
+    "addw",
+    "subw",
+    "sllw",
+    "srlw",
+    "sraw",
+    "csrrw",
+    "csrrs",
+    "csrrc",
+    "csrrwi",
+    "csrrsi",
+    "csrrci",
+    "mul",
+    "mulh",
+    "mulhsu",
+    "mulhu",
+    "div",
+    "divu",
+    "rem",
+    "remu",
+    "mulw",
+    "divw",
+    "divuw",
+    "remw",
+    "remuw",
+    "lr",
+    "sc",
+    "amoswap",
+    "amoadd",
+    "amoxor",
+    "amoand",
+    "amoor",
+    "amomin",
+    "amomax",
+    "amominu",
+    "amomaxu",
+    "flw",
+    "fsw",
+    "fld",
+    "fsd",
+    "fmadd",
+    "fmsub",
+    "fnmsub",
+    "fnmadd",
+    "fadd",
+    "fsub",
+    "fmul",
+    "fdiv",
+    "fsqrt",
+    "fsgnj",
+    "fsgnjn",
+    "fsgnjx",
+    "fmin",
+    "fmax",
+    "fcvt",
+    "fmv",
+    "feq",
+    "flt",
+    "fle",
+    "fclass",
+    "mret",
+    "sret",
+    "wfi",
+    "sfence",
+    "nop",
+    "li",
+    "la",
+    "lla",
+    "mv",
+    "not",
+    "neg",
+    "negw",
+    "sext",
+    "zext",
+    "seqz",
+    "snez",
+    "sltz",
+    "sgtz",
+    "beqz",
+    "bnez",
+    "blez",
+    "bgez",
+    "bltz",
+    "bgtz",
+    "bgt",
+    "ble",
+    "bgtu",
+    "bleu",
+    "j",
+    "jr",
+    "ret",
+    "call",
+    "tail",
+    "fabs",
+    "fneg",
+    "csrr",
+    "csrw",
+    "csrs",
+    "csrc",
+    "rdcycle",
+    "rdtime",
+    "rdinstret",
+    "csrwi",
+    "csrsi",
+    "csrci",
+];
+
+// MIPS32 with the format suffixes (e.g. `.s`, `.d`) stripped
+const MIPS_MNEMONICS: [&str; 121] = [
+    "add", "addu", "addi", "addiu", "sub", "subu", "mult", "multu", "div", "divu", "mfhi", "mflo",
+    "mthi", "mtlo", "mul", "madd", "maddu", "msub", "msubu", "clo", "clz", "and", "andi", "or",
+    "ori", "xor", "xori", "nor", "sll", "srl", "sra", "sllv", "srlv", "srav", "slt", "slti",
+    "sltu", "sltiu", "lui", "movn", "movz", "lb", "lbu", "lh", "lhu", "lw", "lwl", "lwr", "sb",
+    "sh", "sw", "swl", "swr", "ll", "sc", "beq", "bne", "bgez", "bgtz", "blez", "bltz", "bgezal",
+    "bltzal", "j", "jal", "jr", "jalr", "syscall", "break", "eret", "teq", "tne", "tge", "tlt",
+    "mfc0", "mtc0", "mfc1", "mtc1", "lwc1", "swc1", "ldc1", "sdc1", "l", "s", "abs", "neg", "mov",
+    "cvt", "c", "sqrt", "bc1t", "bc1f", "round", "trunc", "ceil", "floor", "nop", "li", "la",
+    "move", "not", "negu", "b", "bal", "beqz", "bnez", "blt", "bgt", "ble", "bge", "bltu", "bgtu",
+    "bleu", "bgeu", "seq", "sne", "sge", "sgt", "sle", "rem", "remu",
+];
+
+// common assembler directives, others share a single kind
+const DIRECTIVES: [&str; 35] = [
+    "text", "data", "rodata", "bss", "section", "globl", "global", "local", "extern", "align",
+    "balign", "p2align", "byte", "half", "short", "word", "dword", "quad", "float", "double",
+    "space", "zero", "ascii", "asciz", "asciiz", "string", "equ", "set", "type", "size", "option",
+    "macro", "endm", "ent", "end",
+];
+
+// longer operators first, so that the longest one matches
+const OPERATORS: [&str; 17] = [
+    "<<", ">>", "(", ")", ",", ":", ";", "+", "-", "*", "/", "%", "&", "|", "^", "~", "=",
+];
+
+// kind: 0 for labels and other symbols, [1, 4] for literals and directives
+const IMMEDIATE: Kind = 1;
+const STRING: Kind = 2;
+const RELOCATION: Kind = 3;
+const DIRECTIVE: Kind = 4;
+// kind: register classes, then operators, directives and mnemonics
+const REGISTER_BASE: Kind = 5;
+const OPERATOR_BASE: Kind = REGISTER_BASE + RegisterClass::FloatSaved as Kind + 1;
+const UNKNOWN: Kind = OPERATOR_BASE + OPERATORS.len() as Kind;
+const DIRECTIVE_BASE: Kind = UNKNOWN + 1;
+const MNEMONIC_BASE: Kind = DIRECTIVE_BASE + DIRECTIVES.len() as Kind;
+
+impl Isa {
+    fn mnemonics(&self) -> &'static [&'static str] {
+        match self {
+            Isa::RiscV => &RISCV_MNEMONICS,
+            Isa::Mips => &MIPS_MNEMONICS,
+        }
+    }
+
+    // register class by its name or number, without `$` for MIPS
+    fn register(&self, name: &str) -> Option<RegisterClass> {
+        use RegisterClass::*;
+        // split e.g. `s11` into `s` and 11
+        let split = name
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(name.len());
+        let (prefix, number) = name.split_at(split);
+        let number: Option<u32> = if number.is_empty() {
+            None
+        } else {
+            number.parse().ok()
+        };
+        match self {
+            Isa::RiscV => match (prefix, number) {
+                ("zero", None) => Some(Zero),
+                ("ra", None) => Some(ReturnAddress),
+                ("sp", None) => Some(StackPointer),
+                ("gp", None) => Some(GlobalPointer),
+                ("tp", None) => Some(ThreadPointer),
+                ("fp", None) => Some(Saved),
+                ("t", Some(0..=6)) => Some(Temporary),
+                ("s", Some(0..=11)) => Some(Saved),
+                ("a", Some(0..=7)) => Some(Argument),
+                ("ft", Some(0..=11)) => Some(FloatTemporary),
+                ("fs", Some(0..=11)) => Some(FloatSaved),
+                ("fa", Some(0..=7)) => Some(FloatArgument),
+                ("x", Some(n)) => match n {
+                    0 => Some(Zero),
+                    1 => Some(ReturnAddress),
+                    2 => Some(StackPointer),
+                    3 => Some(GlobalPointer),
+                    4 => Some(ThreadPointer),
+                    5..=7 | 28..=31 => Some(Temporary),
+                    8 | 9 | 18..=27 => Some(Saved),
+                    10..=17 => Some(Argument),
+                    _ => None,
+                },
+                ("f", Some(n)) => match n {
+                    0..=7 | 28..=31 => Some(FloatTemporary),
+                    8 | 9 | 18..=27 => Some(FloatSaved),
+                    10..=17 => Some(FloatArgument),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Isa::Mips => match (prefix, number) {
+                ("zero", None) => Some(Zero),
+                ("at", None) => Some(AssemblerTemporary),
+                ("gp", None) => Some(GlobalPointer),
+                ("sp", None) => Some(StackPointer),
+                ("fp", None) => Some(Saved),
+                ("ra", None) => Some(ReturnAddress),
+                ("v", Some(0..=1)) => Some(ReturnValue),
+                ("a", Some(0..=3)) => Some(Argument),
+                ("t", Some(0..=9)) => Some(Temporary),
+                ("s", Some(0..=8)) => Some(Saved),
+                ("k", Some(0..=1)) => Some(Kernel),
+                ("", Some(n)) => match n {
+                    0 => Some(Zero),
+                    1 => Some(AssemblerTemporary),
+                    2 | 3 => Some(ReturnValue),
+                    4..=7 => Some(Argument),
+                    8..=15 | 24 | 25 => Some(Temporary),
+                    16..=23 | 30 => Some(Saved),
+                    26 | 27 => Some(Kernel),
+                    28 => Some(GlobalPointer),
+                    29 => Some(StackPointer),
+                    31 => Some(ReturnAddress),
+                    _ => None,
+                },
+                // o32 floating point convention
+                ("f", Some(n)) => match n {
+                    0..=11 | 16..=19 => Some(FloatTemporary),
+                    12..=15 => Some(FloatArgument),
+                    20..=31 => Some(FloatSaved),
+                    _ => None,
+                },
+                _ => None,
+            },
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+// whether the next character other than spaces is a colon, i.e. a label definition
+fn is_followed_by_colon(chars: &[char], mut j: usize) -> bool {
+    while j < chars.len() && (chars[j] == ' ' || chars[j] == '\t') {
+        j += 1;
+    }
+    chars.get(j) == Some(&':')
+}
+
+pub fn tokenize_str(content: &str, isa: Isa) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut res = vec![];
+    let mut i = 0;
+    let mut pos = Position::new();
+    // whether the next word is a mnemonic or directive
+    let mut statement_start = true;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // drop whitespace
+        if c.is_whitespace() {
+            if c == '\n' {
+                statement_start = true;
+            }
+            pos.advance(&chars[i..i + 1]);
+            i += 1;
+            continue;
+        }
+
+        // drop comments
+        if c == '#' || (c == '/' && chars.get(i + 1) == Some(&'/')) {
+            let mut end = i;
+            while end < chars.len() && chars[end] != '\n' {
+                end += 1;
+            }
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let mut end = i + 2;
+            while end + 1 < chars.len() && !(chars[end] == '*' && chars[end + 1] == '/') {
+                end += 1;
+            }
+            let end = (end + 2).min(chars.len());
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        let end;
+        let kind;
+        if c.is_ascii_digit() {
+            let mut j = i;
+            while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '.') {
+                j += 1;
+            }
+            let word: String = chars[i..j].iter().collect();
+            let is_local_reference = (word.ends_with('b') || word.ends_with('f'))
+                && word[..word.len() - 1].chars().all(|c| c.is_ascii_digit());
+            if (statement_start && is_followed_by_colon(&chars, j)) || is_local_reference {
+                // numeric local label: `1:`, referenced by `1b` or `1f`
+                kind = 0;
+            } else {
+                kind = IMMEDIATE;
+            }
+            end = j;
+        } else if c == '\'' {
+            // character immediate: 'a', '\n'
+            let mut j = i + 1;
+            if chars.get(j) == Some(&'\\') {
+                j += 1;
+            }
+            j += 1;
+            if chars.get(j) == Some(&'\'') {
+                j += 1;
+            }
+            end = j.min(chars.len());
+            kind = IMMEDIATE;
+        } else if c == '"' {
+            // string literal with backslash escapes
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '"' && chars[j] != '\n' {
+                if chars[j] == '\\' {
+                    j += 1;
+                }
+                j += 1;
+            }
+            end = (j + 1).min(chars.len());
+            kind = STRING;
+        } else if c == '%' && chars.get(i + 1).is_some_and(|c| c.is_ascii_alphabetic()) {
+            // relocation function: %hi(sym), %pcrel_lo(label)
+            let mut j = i + 1;
+            while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                j += 1;
+            }
+            end = j;
+            kind = RELOCATION;
+        } else if is_identifier_char(c) {
+            let mut j = i;
+            while j < chars.len() && is_identifier_char(chars[j]) {
+                j += 1;
+            }
+            let word = chars[i..j].iter().collect::<String>().to_ascii_lowercase();
+            if statement_start && is_followed_by_colon(&chars, j) {
+                // label definition
+                kind = 0;
+            } else if statement_start {
+                statement_start = false;
+                if let Some(directive) = word.strip_prefix('.') {
+                    kind = match DIRECTIVES.iter().position(|d| *d == directive) {
+                        Some(index) => DIRECTIVE_BASE + index as Kind,
+                        None => DIRECTIVE,
+                    };
+                } else {
+                    let base = word.split('.').next().unwrap_or_default();
+                    let mnemonics = isa.mnemonics();
+                    kind = match mnemonics.iter().position(|m| *m == base) {
+                        Some(index) => MNEMONIC_BASE + index as Kind,
+                        // unknown mnemonic or macro
+                        None => MNEMONIC_BASE + mnemonics.len() as Kind,
+                    };
+                }
+            } else {
+                let register = match isa {
+                    Isa::RiscV => isa.register(&word),
+                    Isa::Mips => word.strip_prefix('$').and_then(|name| isa.register(name)),
+                };
+                kind = match register {
+                    Some(class) => REGISTER_BASE + class as Kind,
+                    // label or other symbol
+                    None => 0,
+                };
+            }
+            end = j;
+        } else if let Some(index) = OPERATORS.iter().position(|op| {
+            op.chars()
+                .enumerate()
+                .all(|(k, ch)| chars.get(i + k) == Some(&ch))
+        }) {
+            end = i + OPERATORS[index].chars().count();
+            kind = OPERATOR_BASE + index as Kind;
+            if c == ';' {
+                // statement separator
+                statement_start = true;
+            }
+        } else {
+            end = i + 1;
+            kind = UNKNOWN;
+        }
+
+        let start = pos;
+        pos.advance(&chars[i..end]);
+        res.push(start.token(&pos, kind, chars[i..end].iter().collect()));
+        i = end;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize_str, Isa};
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "main:\n  addi sp, sp, -16 # prologue\n  la a0, msg\n  call puts\n  ret";
+        let tokens = tokenize_str(code, Isa::RiscV).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "main");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[2].spelling, "addi");
+        assert_eq!(tokens[2].line, 2);
+        assert_eq!(tokens[2].column, 3);
+
+        assert_eq!(tokens[3].spelling, "sp");
+        assert_eq!(tokens[3].line, 2);
+        assert_eq!(tokens[3].column, 8);
+
+        assert_eq!(tokens[8].spelling, "16");
+        assert_eq!(tokens[8].line, 2);
+        assert_eq!(tokens[8].column, 17);
+
+        // comment is dropped
+        assert_eq!(tokens[9].spelling, "la");
+        assert_eq!(tokens[9].line, 3);
+        assert_eq!(tokens[9].column, 3);
+
+        assert_eq!(tokens[15].spelling, "ret");
+        assert_eq!(tokens[15].line, 5);
+        assert_eq!(tokens[15].column, 3);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let left = tokenize_str(
+            "loop:\n  lw t0, 0(a0)\n  add s1, s1, t0\n  addi a0, a0, 4\n  bnez t0, loop",
+            Isa::RiscV,
+        )
+        .unwrap();
+        let right = tokenize_str(
+            ".L2: lw t3, 8(a1)\n  add s4, s4, t3\n  addi a1, a1, 0x10\n  bnez x28, .L2",
+            Isa::RiscV,
+        )
+        .unwrap();
+        let left_kind: Vec<Kind> = left.iter().map(|t| t.kind).collect();
+        let right_kind: Vec<Kind> = right.iter().map(|t| t.kind).collect();
+        assert_eq!(left_kind, right_kind);
+
+        // register classes and mnemonics are distinguished
+        let other = tokenize_str(
+            "loop:\n  lw s0, 0(a0)\n  sub s1, s1, s0\n  addi a0, a0, 4\n  bnez s0, loop",
+            Isa::RiscV,
+        )
+        .unwrap();
+        let other_kind: Vec<Kind> = other.iter().map(|t| t.kind).collect();
+        assert_ne!(left_kind, other_kind);
+    }
+
+    #[test]
+    fn test_mips() {
+        let left = tokenize_str(
+            "main:\n\tli $v0, 4\n\tla $a0, msg\n\tsyscall\n\tjr $ra",
+            Isa::Mips,
+        )
+        .unwrap();
+        let right = tokenize_str(
+            "start: li $2, 4 # print string\n\tla $4, str\n\tsyscall\n\tjr $31",
+            Isa::Mips,
+        )
+        .unwrap();
+        let left_kind: Vec<Kind> = left.iter().map(|t| t.kind).collect();
+        let right_kind: Vec<Kind> = right.iter().map(|t| t.kind).collect();
+        assert_eq!(left_kind, right_kind);
+    }
+}
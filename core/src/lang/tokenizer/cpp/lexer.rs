@@ -0,0 +1,384 @@
+//! Built-in C/C++ lexer, classifying tokens the way libclang does
+use super::super::scanner::Position;
+use super::{keyword_kind, punctuation_kind, Dialect};
+use crate::token::Token;
+
+// keywords shared by C and C++, including GNU extensions
+pub(super) const COMMON_KEYWORDS: [&str; 59] = [
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "_Alignas",
+    "_Alignof",
+    "_Atomic",
+    "_Complex",
+    "_Generic",
+    "_Imaginary",
+    "_Noreturn",
+    "_Static_assert",
+    "_Thread_local",
+    "__func__",
+    "asm",
+    "typeof",
+    "__asm",
+    "__asm__",
+    "__attribute",
+    "__attribute__",
+    "__const",
+    "__const__",
+    "__extension__",
+    "__inline",
+    "__inline__",
+    "__int128",
+    "__restrict",
+    "__restrict__",
+    "__typeof__",
+    "__volatile__",
+];
+
+pub(super) const C_KEYWORDS: [&str; 2] = ["restrict", "_Bool"];
+
+// C++17, the default of clang
+pub(super) const CPP_KEYWORDS: [&str; 58] = [
+    "alignas",
+    "alignof",
+    "and",
+    "and_eq",
+    "bitand",
+    "bitor",
+    "bool",
+    "catch",
+    "char16_t",
+    "char32_t",
+    "class",
+    "compl",
+    "const_cast",
+    "constexpr",
+    "decltype",
+    "delete",
+    "dynamic_cast",
+    "explicit",
+    "export",
+    "false",
+    "friend",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "not",
+    "not_eq",
+    "nullptr",
+    "operator",
+    "or",
+    "or_eq",
+    "private",
+    "protected",
+    "public",
+    "reinterpret_cast",
+    "static_assert",
+    "static_cast",
+    "template",
+    "this",
+    "thread_local",
+    "throw",
+    "true",
+    "try",
+    "typeid",
+    "typename",
+    "using",
+    "virtual",
+    "wchar_t",
+    "xor",
+    "xor_eq",
+    "__alignof",
+    "__alignof__",
+    "__decltype",
+    "__null",
+    "__typeof",
+    "__is_same",
+    "__is_class",
+    "__is_enum",
+];
+
+// longer punctuators first, so that the longest one matches
+pub(super) const PUNCTUATORS: [&str; 57] = [
+    "%:%:", ">>=", "<<=", "->*", "...", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&",
+    "||", "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=", "::", ".*", "##", "<:", ":>", "<%", "%>",
+    "%:", "{", "}", "[", "]", "(", ")", ";", ":", ",", ".", "?", "~", "!", "+", "-", "*", "/", "%",
+    "^", "&", "|", "=", "<", ">", "#",
+];
+
+// C has no `::`, `.*` or `->*`
+const CPP_ONLY_PUNCTUATORS: [&str; 3] = ["::", ".*", "->*"];
+
+fn is_keyword(word: &str, dialect: Dialect) -> bool {
+    COMMON_KEYWORDS.contains(&word)
+        || match dialect {
+            Dialect::C => C_KEYWORDS.contains(&word),
+            Dialect::Cpp => CPP_KEYWORDS.contains(&word),
+        }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn starts_with(chars: &[char], i: usize, text: &str) -> bool {
+    text.chars()
+        .enumerate()
+        .all(|(k, ch)| chars.get(i + k) == Some(&ch))
+}
+
+// end of a quoted literal whose opening quote is at `i`
+fn quoted_end(chars: &[char], i: usize) -> usize {
+    let quote = chars[i];
+    let mut j = i + 1;
+    while j < chars.len() && chars[j] != '\n' {
+        if chars[j] == '\\' {
+            j += 2;
+            continue;
+        }
+        if chars[j] == quote {
+            return j + 1;
+        }
+        j += 1;
+    }
+    j.min(chars.len())
+}
+
+// end of a raw string literal R"delim(...)delim" whose quote is at `i`
+fn raw_string_end(chars: &[char], i: usize) -> usize {
+    let mut j = i + 1;
+    while j < chars.len() && chars[j] != '(' && chars[j] != '\n' {
+        j += 1;
+    }
+    let delimiter: String = chars[i + 1..j].iter().collect();
+    let close = format!("){}\"", delimiter);
+    while j < chars.len() {
+        if starts_with(chars, j, &close) {
+            return j + close.chars().count();
+        }
+        j += 1;
+    }
+    chars.len()
+}
+
+pub fn tokenize_str(content: &str, dialect: Dialect) -> Vec<Token> {
+    lex(content, dialect)
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect()
+}
+
+/// Tokens with whether each starts a logical line, for preprocessor directives
+pub(crate) fn lex(content: &str, dialect: Dialect) -> Vec<(Token, bool)> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut res: Vec<(Token, bool)> = vec![];
+    let mut i = 0;
+    let mut pos = Position::new();
+    let mut line_start = true;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // drop whitespace and line splices
+        if c.is_whitespace() || (c == '\\' && chars.get(i + 1) == Some(&'\n')) {
+            let end = if c == '\\' { i + 2 } else { i + 1 };
+            if c == '\n' {
+                line_start = true;
+            }
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        // drop comments
+        if starts_with(&chars, i, "//") || starts_with(&chars, i, "/*") {
+            let mut end = i + 2;
+            if c == '/' && chars[i + 1] == '/' {
+                // a splice continues the comment to the next line
+                while end < chars.len() && (chars[end] != '\n' || chars[end - 1] == '\\') {
+                    end += 1;
+                }
+            } else {
+                while end < chars.len() && !starts_with(&chars, end, "*/") {
+                    end += 1;
+                }
+                end = (end + 2).min(chars.len());
+            }
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        let end;
+        let kind;
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            // pp-number: 42, 0x1F, 1.5e-3f, 1'000'000, 10_km
+            let mut j = i + 1;
+            while j < chars.len() {
+                let d = chars[j];
+                let is_exponent_sign =
+                    (d == '+' || d == '-') && matches!(chars[j - 1], 'e' | 'E' | 'p' | 'P');
+                if is_identifier_char(d) || d == '.' || is_exponent_sign {
+                    j += 1;
+                } else if d == '\'' && chars.get(j + 1).is_some_and(|c| c.is_alphanumeric()) {
+                    j += 2;
+                } else {
+                    break;
+                }
+            }
+            end = j;
+            kind = 1;
+        } else if is_identifier_start(c) {
+            let mut j = i;
+            while j < chars.len() && is_identifier_char(chars[j]) {
+                j += 1;
+            }
+            let word: String = chars[i..j].iter().collect();
+            let next = chars.get(j).copied();
+            if matches!(word.as_str(), "L" | "u" | "U" | "u8") && matches!(next, Some('\'' | '"')) {
+                // prefixed character or string literal
+                end = quoted_end(&chars, j);
+                kind = 1;
+            } else if dialect == Dialect::Cpp
+                && matches!(word.as_str(), "R" | "LR" | "uR" | "UR" | "u8R")
+                && next == Some('"')
+            {
+                end = raw_string_end(&chars, j);
+                kind = 1;
+            } else {
+                end = j;
+                kind = if is_keyword(&word, dialect) {
+                    keyword_kind(&word)
+                } else {
+                    0
+                };
+            }
+        } else if c == '\'' || c == '"' {
+            end = quoted_end(&chars, i);
+            kind = 1;
+        } else {
+            let punctuator = PUNCTUATORS
+                .iter()
+                .filter(|p| dialect == Dialect::Cpp || !CPP_ONLY_PUNCTUATORS.contains(p))
+                .find(|p| starts_with(&chars, i, p));
+            end = match punctuator {
+                Some(p) => i + p.chars().count(),
+                // everything else, e.g. `@` and stray characters
+                None => i + 1,
+            };
+            let spelling: String = chars[i..end].iter().collect();
+            kind = punctuation_kind(&spelling);
+        }
+
+        // user-defined literal suffixes in C++
+        let mut end = end;
+        if kind == 1 && dialect == Dialect::Cpp && matches!(chars[end - 1], '\'' | '"') {
+            while end < chars.len() && is_identifier_char(chars[end]) {
+                end += 1;
+            }
+        }
+
+        let start = pos;
+        pos.advance(&chars[i..end]);
+        res.push((
+            start.token(&pos, kind, chars[i..end].iter().collect()),
+            line_start,
+        ));
+        line_start = false;
+        i = end;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize_str;
+    use crate::lang::tokenizer::cpp::{keyword_kind, punctuation_kind, Dialect};
+
+    #[test]
+    fn test_tokenize() {
+        let code = "#include <stdio.h>\nint main() {\n  // comment\n  auto s = u8\"a\\\"b\"_x; /* block\n */ a->*b <<= 1'000;\n}";
+        let tokens = tokenize_str(code, Dialect::Cpp);
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "#");
+        assert_eq!(tokens[1].spelling, "include");
+        assert_eq!(tokens[1].kind, 0);
+        assert_eq!(tokens[2].spelling, "<");
+        assert_eq!(tokens[2].kind, punctuation_kind("<"));
+
+        assert_eq!(tokens[7].spelling, "int");
+        assert_eq!(tokens[7].kind, keyword_kind("int"));
+        assert_eq!(tokens[7].line, 2);
+        assert_eq!(tokens[7].column, 1);
+
+        // comments are dropped, prefixes and suffixes are part of the literal
+        assert_eq!(tokens[12].spelling, "auto");
+        assert_eq!(tokens[12].line, 4);
+        assert_eq!(tokens[15].spelling, "u8\"a\\\"b\"_x");
+        assert_eq!(tokens[15].kind, 1);
+        assert_eq!(tokens[15].column, 12);
+
+        assert_eq!(tokens[17].spelling, "a");
+        assert_eq!(tokens[17].line, 5);
+        assert_eq!(tokens[17].column, 5);
+        assert_eq!(tokens[18].spelling, "->*");
+        assert_eq!(tokens[20].spelling, "<<=");
+        assert_eq!(tokens[21].spelling, "1'000");
+        assert_eq!(tokens[21].kind, 1);
+    }
+
+    #[test]
+    fn test_dialect() {
+        let code = "class x; a::b; R\"(raw)\";";
+        let cpp = tokenize_str(code, Dialect::Cpp);
+        let c = tokenize_str(code, Dialect::C);
+
+        assert_eq!(cpp[0].kind, keyword_kind("class"));
+        assert_eq!(c[0].kind, 0);
+
+        assert_eq!(cpp[4].spelling, "::");
+        assert_eq!(c[4].spelling, ":");
+
+        assert_eq!(cpp[7].spelling, "R\"(raw)\"");
+        assert_eq!(c[8].spelling, "R");
+    }
+}
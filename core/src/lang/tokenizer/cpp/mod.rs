@@ -0,0 +1,320 @@
+use super::fallback;
+use crate::common::stable_hash;
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+use anyhow::anyhow;
+use std::{ops::Range, path::Path, str::FromStr, sync::RwLock};
+
+pub mod lexer;
+pub mod preprocess;
+
+/// How C/C++ sources are lexed, both giving the same kinds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// built-in lexer, without system libraries or temporary files, and safe
+    /// to run on many threads
+    #[default]
+    Builtin,
+    /// libclang, limited to one instance per process
+    #[cfg(feature = "clang")]
+    Clang,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "builtin" => Ok(Backend::Builtin),
+            #[cfg(feature = "clang")]
+            "clang" | "libclang" => Ok(Backend::Clang),
+            #[cfg(not(feature = "clang"))]
+            "clang" | "libclang" => Err(anyhow!(
+                "libclang is not available, build with the `clang` feature of `core`"
+            )),
+            _ => Err(anyhow!("Unknown C/C++ backend: {}", s)),
+        }
+    }
+}
+
+// backend of the C/C++ tokenizers of `lang::tokenize` and friends
+static BACKEND: RwLock<Backend> = RwLock::new(Backend::Builtin);
+
+/// Select the backend of the C and C++ languages, e.g. from a command line
+/// option, for files tokenized from then on
+pub fn set_backend(backend: Backend) {
+    *BACKEND.write().unwrap() = backend;
+}
+
+/// Backend of the C and C++ languages, the built-in lexer unless set
+pub fn backend() -> Backend {
+    *BACKEND.read().unwrap()
+}
+
+/// C has fewer keywords and punctuators, e.g. `class` and `new` are
+/// identifiers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    C,
+    #[default]
+    Cpp,
+}
+
+#[derive(Default)]
+pub struct Cpp {
+    pub backend: Backend,
+    pub dialect: Dialect,
+    /// tokenize the preprocessed source with the built-in lexer, so that
+    /// macros are expanded, regardless of the backend
+    pub preprocess: Option<preprocess::Options>,
+}
+
+impl Cpp {
+    // the built-in lexer never fails, so it takes over when the others do
+    fn or_builtin(&self, content: &str, tokens: anyhow::Result<Vec<Token>>) -> Tokenized {
+        match tokens {
+            Ok(tokens) => tokens.into(),
+            Err(err) => Tokenized {
+                tokens: lexer::tokenize_str(content, self.dialect),
+                diagnostics: vec![fallback::diagnostic(err)],
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Tokenize for Cpp {
+    fn tokenize_content(&self, path: &Path, content: &str) -> Tokenized {
+        let tokens = if let Some(options) = &self.preprocess {
+            preprocess::tokenize(content, Some(path), self.dialect, options)
+        } else {
+            match self.backend {
+                Backend::Builtin => Ok(lexer::tokenize_str(content, self.dialect)),
+                // the decoded content rather than the file, so that positions
+                // agree with the built-in lexer
+                #[cfg(feature = "clang")]
+                Backend::Clang => libclang::tokenize_str(content, self.dialect),
+            }
+        };
+        self.or_builtin(content, tokens)
+    }
+
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        let tokens = if let Some(options) = &self.preprocess {
+            preprocess::tokenize(content, None, self.dialect, options)
+        } else {
+            match self.backend {
+                Backend::Builtin => return lexer::tokenize_str(content, self.dialect).into(),
+                #[cfg(feature = "clang")]
+                Backend::Clang => libclang::tokenize_str(content, self.dialect),
+            }
+        };
+        self.or_builtin(content, tokens)
+    }
+}
+
+// kinds are positions in the lexer's tables, which must only be extended at
+// the end, bumping the kind schema of C and C++; spellings missing from them,
+// e.g. extensions reported by libclang, are hashed into `unknown`
+fn table_kind(tables: &[&[&str]], spelling: &str, base: Kind, unknown: Range<Kind>) -> Kind {
+    let mut index = 0;
+    for table in tables {
+        if let Some(position) = table.iter().position(|s| *s == spelling) {
+            return base + (index + position) as Kind;
+        }
+        index += table.len();
+    }
+    let len = (unknown.end - unknown.start) as u64;
+    unknown.start + (stable_hash(spelling.as_bytes()) % len) as Kind
+}
+
+/// Keyword: [2, 128], others: [0x1000, 0x2000)
+pub(crate) fn keyword_kind(spelling: &str) -> Kind {
+    table_kind(
+        &[
+            &lexer::COMMON_KEYWORDS,
+            &lexer::C_KEYWORDS,
+            &lexer::CPP_KEYWORDS,
+        ],
+        spelling,
+        2,
+        0x1000..0x2000,
+    )
+}
+
+/// Punctuation: [129, 255], others: [0x2000, 0x3000)
+pub(crate) fn punctuation_kind(spelling: &str) -> Kind {
+    table_kind(&[&lexer::PUNCTUATORS], spelling, 129, 0x2000..0x3000)
+}
+
+#[cfg(feature = "clang")]
+mod libclang {
+    use super::{keyword_kind, punctuation_kind, Dialect};
+    use crate::lang::tokenizer::scanner::LineIndex;
+    use crate::token::Token;
+    use anyhow::anyhow;
+    use clang::token::TokenKind;
+    use std::{path::Path, sync::Mutex};
+    use tempfile::tempdir;
+
+    // libclang fails rather than waits while another instance exists, so
+    // files tokenized on several threads take turns
+    static CLANG: Mutex<()> = Mutex::new(());
+
+    // tokens of the file at `path`, whose content is `content`
+    fn tokenize(path: &Path, content: &str, dialect: Dialect) -> anyhow::Result<Vec<Token>> {
+        let _guard = CLANG.lock().unwrap_or_else(|err| err.into_inner());
+        let clang = clang::Clang::new().map_err(|err| anyhow!("{}", err))?;
+        let index = clang::Index::new(&clang, true, false);
+        // otherwise clang guesses from the extension, taking `.h` as C
+        let language = match dialect {
+            Dialect::C => "c",
+            Dialect::Cpp => "c++",
+        };
+        let tu = index.parser(path).arguments(&["-x", language]).parse()?;
+        let lines = LineIndex::new(content);
+        let mut vector = vec![];
+        if let Some(range) = tu.get_entity().get_range() {
+            for token in range.tokenize() {
+                let kind = match token.get_kind() {
+                    TokenKind::Comment => continue,
+                    TokenKind::Identifier => 0x0,
+                    TokenKind::Literal => 0x1,
+                    TokenKind::Keyword => keyword_kind(&token.get_spelling()),
+                    TokenKind::Punctuation => punctuation_kind(&token.get_spelling()),
+                };
+
+                // libclang counts columns in bytes, so positions are taken
+                // from the offsets
+                let range = token.get_range();
+                let start = range.get_start().get_file_location().offset as usize;
+                let end = range.get_end().get_file_location().offset as usize;
+                vector.push(lines.token(kind, token.get_spelling(), start..end));
+            }
+        }
+        Ok(vector)
+    }
+
+    pub fn tokenize_str(content: &str, dialect: Dialect) -> anyhow::Result<Vec<Token>> {
+        let dir = tempdir()?;
+        let path = dir.path().join(match dialect {
+            Dialect::C => "code.c",
+            Dialect::Cpp => "code.cpp",
+        });
+        std::fs::write(&path, content)?;
+        tokenize(&path, content, dialect)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::tokenize_str;
+        use crate::lang::tokenizer::cpp::{lexer, Dialect};
+        use crate::token::Kind;
+
+        #[test]
+        fn test_tokenize() {
+            let code = "int main() { return 0; }";
+            let tokens = tokenize_str(code, Dialect::Cpp).unwrap();
+
+            eprintln!("{:?}", tokens);
+
+            assert_eq!(tokens[0].spelling, "int");
+            assert_eq!(tokens[0].line, 1);
+            assert_eq!(tokens[0].column, 1);
+
+            assert_eq!(tokens[1].spelling, "main");
+            assert_eq!(tokens[1].line, 1);
+            assert_eq!(tokens[1].column, 5);
+
+            assert_eq!(tokens[2].spelling, "(");
+            assert_eq!(tokens[2].line, 1);
+            assert_eq!(tokens[2].column, 9);
+
+            assert_eq!(tokens[3].spelling, ")");
+            assert_eq!(tokens[3].line, 1);
+            assert_eq!(tokens[3].column, 10);
+
+            assert_eq!(tokens[8].spelling, "}");
+            assert_eq!(tokens[8].line, 1);
+            assert_eq!(tokens[8].column, 24);
+        }
+
+        #[test]
+        fn test_columns() {
+            // in characters, as with the other tokenizers
+            let tokens = tokenize_str("/* é */ int x;", Dialect::C).unwrap();
+            assert_eq!(tokens[0].spelling, "int");
+            assert_eq!(tokens[0].column, 9);
+            assert_eq!(tokens[0].end_column, 12);
+            assert_eq!(tokens[0].bytes, 9..12);
+        }
+
+        #[test]
+        fn test_builtin() {
+            // both backends give the same kinds
+            let code = "#include <stdio.h>\nint main() { printf(\"%d\\n\", 1 << 2); }";
+            for dialect in [Dialect::C, Dialect::Cpp] {
+                let clang: Vec<Kind> = tokenize_str(code, dialect)
+                    .unwrap()
+                    .iter()
+                    .map(|t| t.kind)
+                    .collect();
+                let builtin: Vec<Kind> = lexer::tokenize_str(code, dialect)
+                    .iter()
+                    .map(|t| t.kind)
+                    .collect();
+                assert_eq!(clang, builtin);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keyword_kind, lexer, punctuation_kind};
+    use crate::lang::{tokenize_str, Language};
+    use crate::token::Kind;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_language() {
+        let code = "struct class *new = 0;";
+        let c = tokenize_str(code, Language::C).unwrap().tokens;
+        let cpp = tokenize_str(code, Language::Cpp).unwrap().tokens;
+
+        assert_eq!(c[0].kind, keyword_kind("struct"));
+        assert_eq!(c[1].spelling, "class");
+        assert_eq!(c[1].kind, 0);
+        assert_eq!(c[3].spelling, "new");
+        assert_eq!(c[3].kind, 0);
+
+        assert_eq!(cpp[1].kind, keyword_kind("class"));
+        assert_eq!(cpp[3].kind, keyword_kind("new"));
+    }
+
+    #[test]
+    fn test_kinds() {
+        // saved kinds depend on these, bump the kind schema if they change
+        assert_eq!(keyword_kind("auto"), 2);
+        assert_eq!(keyword_kind("restrict"), 61);
+        assert_eq!(keyword_kind("class"), 73);
+        assert_eq!(punctuation_kind("%:%:"), 129);
+        assert_eq!(punctuation_kind("#"), 185);
+
+        let keywords = lexer::COMMON_KEYWORDS
+            .iter()
+            .chain(&lexer::C_KEYWORDS)
+            .chain(&lexer::CPP_KEYWORDS);
+        let kinds: HashSet<Kind> = keywords.clone().map(|k| keyword_kind(k)).collect();
+        assert_eq!(kinds.len(), keywords.count());
+        let kinds: HashSet<Kind> = lexer::PUNCTUATORS
+            .iter()
+            .map(|p| punctuation_kind(p))
+            .collect();
+        assert_eq!(kinds.len(), lexer::PUNCTUATORS.len());
+
+        // unknown spellings stay in range
+        assert!((0x1000..0x2000).contains(&keyword_kind("__builtin_va_arg")));
+        assert!((0x2000..0x3000).contains(&punctuation_kind("@")));
+    }
+}
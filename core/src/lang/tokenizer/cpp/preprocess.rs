@@ -0,0 +1,961 @@
+//! A small C preprocessor over the built-in lexer, so that macros cannot hide
+//! keywords or loops. Tokens produced by a macro are located at its
+//! invocation, and tokens of the student's own headers at their `#include`;
+//! other headers only contribute their macros.
+use super::lexer::lex;
+use super::Dialect;
+use crate::common::split_command;
+use crate::decode;
+use crate::token::Token;
+use anyhow::anyhow;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+// guards against runaway recursion
+const MAX_INCLUDE_DEPTH: usize = 64;
+const MAX_EXPANSIONS: usize = 100000;
+
+/// Include directories and macros, as given to the compiler
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub include_dirs: Vec<PathBuf>,
+    /// `NAME` or `NAME=VALUE`, as in `-D`
+    pub defines: Vec<String>,
+    /// as in `-U`
+    pub undefines: Vec<String>,
+    /// directory of the student's own files: tokens of headers under it that
+    /// are included with quotes are kept; defaults to the directory of the
+    /// main file
+    pub root: Option<PathBuf>,
+}
+
+impl Options {
+    /// Take `-I`, `-isystem`, `-iquote`, `-D` and `-U` from compiler
+    /// arguments, resolving include directories against `directory`
+    pub fn from_args<S: AsRef<str>>(args: &[S], directory: &Path) -> Self {
+        const FLAGS: [&str; 5] = ["-isystem", "-iquote", "-I", "-D", "-U"];
+        let mut res = Options::default();
+        let mut iter = args.iter().map(|arg| arg.as_ref());
+        while let Some(arg) = iter.next() {
+            let Some(flag) = FLAGS.iter().find(|flag| arg.starts_with(*flag)) else {
+                continue;
+            };
+            // the value is either attached or the next argument
+            let value = if arg.len() > flag.len() {
+                arg[flag.len()..].to_string()
+            } else if let Some(value) = iter.next() {
+                value.to_string()
+            } else {
+                break;
+            };
+            match *flag {
+                "-D" => res.defines.push(value),
+                "-U" => res.undefines.push(value),
+                _ => res.include_dirs.push(directory.join(value)),
+            }
+        }
+        res
+    }
+
+    /// Options of `file` in a `compile_commands.json`: the entry of this very
+    /// file, or else the first whose file ends with the path of `file` under
+    /// `root`, e.g. one student's `src/main.c` for another's in a database
+    /// from a reference build
+    pub fn from_compile_commands(
+        database: &Path,
+        file: &Path,
+        root: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(database)?)?;
+        let entries = json
+            .as_array()
+            .ok_or_else(|| anyhow!("Not a compilation database: {}", database.display()))?;
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let target = canonical(file);
+        let relative = root.and_then(|root| target.strip_prefix(canonical(root)).ok());
+        let paths: Vec<(&Value, PathBuf, PathBuf)> = entries
+            .iter()
+            .filter_map(|entry| {
+                let directory = PathBuf::from(entry["directory"].as_str().unwrap_or("."));
+                let path = canonical(&directory.join(entry["file"].as_str()?));
+                Some((entry, directory, path))
+            })
+            .collect();
+        let found = paths
+            .iter()
+            .find(|(_, _, path)| *path == target)
+            .or_else(|| {
+                let relative = relative.filter(|relative| !relative.as_os_str().is_empty())?;
+                paths.iter().find(|(_, _, path)| path.ends_with(relative))
+            });
+        if let Some((entry, directory, _)) = found {
+            let args: Vec<String> = if let Some(arguments) = entry["arguments"].as_array() {
+                arguments
+                    .iter()
+                    .filter_map(|arg| arg.as_str().map(|s| s.to_string()))
+                    .collect()
+            } else {
+                split_command(entry["command"].as_str().unwrap_or_default())
+            };
+            return Ok(Options::from_args(&args, directory));
+        }
+        Err(anyhow!(
+            "{} not found in {}",
+            file.display(),
+            database.display()
+        ))
+    }
+}
+
+struct Macro {
+    // `None` for object-like macros
+    params: Option<Vec<String>>,
+    // whether the last parameter takes the remaining arguments
+    variadic: bool,
+    body: Vec<Token>,
+}
+
+// token with the macros that may not expand it again
+#[derive(Clone)]
+struct Pending {
+    token: Token,
+    hide: Vec<String>,
+}
+
+fn is_identifier(spelling: &str) -> bool {
+    spelling
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+}
+
+fn is_hash(token: &Token) -> bool {
+    token.spelling == "#" || token.spelling == "%:"
+}
+
+fn is_paste(token: &Token) -> bool {
+    token.spelling == "##" || token.spelling == "%:%:"
+}
+
+// token from the start of `from` to the end of `to`
+fn span(from: &Token, to: &Token) -> Token {
+    if to.bytes.end < from.bytes.end {
+        return from.clone();
+    }
+    Token {
+        end_line: to.end_line,
+        end_column: to.end_column,
+        bytes: from.bytes.start..to.bytes.end,
+        ..from.clone()
+    }
+}
+
+// move a token to where it was produced, e.g. a macro invocation
+fn locate(token: &mut Token, at: &Token) {
+    token.line = at.line;
+    token.column = at.column;
+    token.end_line = at.end_line;
+    token.end_column = at.end_column;
+    token.bytes = at.bytes.clone();
+}
+
+fn literal(spelling: String, at: &Token) -> Token {
+    Token {
+        kind: 1,
+        spelling,
+        ..at.clone()
+    }
+}
+
+struct Preprocessor<'a> {
+    options: &'a Options,
+    dialect: Dialect,
+    macros: HashMap<String, Macro>,
+    // canonical directory of the student's own headers
+    root: Option<PathBuf>,
+    // files with `#pragma once`
+    once: HashSet<PathBuf>,
+    depth: usize,
+    expansions: usize,
+}
+
+/// Tokenize the preprocessed `content`, keeping tokens of `content` itself
+/// and of macros invoked in it; `path` locates quoted includes
+pub fn tokenize(
+    content: &str,
+    path: Option<&Path>,
+    dialect: Dialect,
+    options: &Options,
+) -> anyhow::Result<Vec<Token>> {
+    let root = options.root.clone().or_else(|| {
+        path.and_then(Path::parent).map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir.to_path_buf()
+            }
+        })
+    });
+    let mut preprocessor = Preprocessor {
+        options,
+        dialect,
+        macros: HashMap::new(),
+        root: root.map(|root| root.canonicalize().unwrap_or(root)),
+        once: HashSet::new(),
+        depth: 0,
+        expansions: 0,
+    };
+    let mut predefined = vec!["__STDC__ 1".to_string()];
+    match dialect {
+        Dialect::C => predefined.push("__STDC_VERSION__ 201710L".to_string()),
+        Dialect::Cpp => predefined.push("__cplusplus 201703L".to_string()),
+    }
+    for define in &options.defines {
+        predefined.push(match define.split_once('=') {
+            Some((name, value)) => format!("{} {}", name, value),
+            None => format!("{} 1", define),
+        });
+    }
+    for define in predefined {
+        let tokens: Vec<Token> = lex(&define, dialect).into_iter().map(|(t, _)| t).collect();
+        preprocessor.define(&tokens);
+    }
+    for name in &options.undefines {
+        preprocessor.macros.remove(name);
+    }
+
+    let mut res = vec![];
+    preprocessor.file(content, path, Some(&mut res))?;
+    Ok(res)
+}
+
+impl Preprocessor<'_> {
+    // process a file, appending its expanded tokens to `output` if given
+    fn file(
+        &mut self,
+        content: &str,
+        path: Option<&Path>,
+        mut output: Option<&mut Vec<Token>>,
+    ) -> anyhow::Result<()> {
+        let tokens = lex(content, self.dialect);
+        // for each open conditional: whether it is active, and whether a
+        // branch has been taken
+        let mut conditions: Vec<(bool, bool)> = vec![];
+        let mut text: Vec<Token> = vec![];
+        let mut i = 0;
+        while i < tokens.len() {
+            let (token, line_start) = &tokens[i];
+            let active = conditions.last().is_none_or(|c| c.0);
+            if *line_start && is_hash(token) {
+                // a directive runs to the end of the logical line
+                let mut end = i + 1;
+                while end < tokens.len() && !tokens[end].1 {
+                    end += 1;
+                }
+                let line: Vec<Token> = tokens[i + 1..end].iter().map(|(t, _)| t.clone()).collect();
+                let at = span(token, &tokens[end - 1].0);
+                // macros defined by the directive apply to the text after it
+                if let Some(output) = output.as_deref_mut() {
+                    output.extend(self.expand(std::mem::take(&mut text)));
+                }
+                self.directive(
+                    &line,
+                    &at,
+                    path,
+                    active,
+                    &mut conditions,
+                    output.as_deref_mut(),
+                )?;
+                i = end;
+                continue;
+            }
+            if active && output.is_some() {
+                text.push(token.clone());
+            }
+            i += 1;
+        }
+        if let Some(output) = output {
+            output.extend(self.expand(text));
+        }
+        Ok(())
+    }
+
+    // `at` spans the directive, `output` is that of the file it is in
+    fn directive(
+        &mut self,
+        line: &[Token],
+        at: &Token,
+        path: Option<&Path>,
+        active: bool,
+        conditions: &mut Vec<(bool, bool)>,
+        output: Option<&mut Vec<Token>>,
+    ) -> anyhow::Result<()> {
+        let Some(name) = line.first() else {
+            // null directive
+            return Ok(());
+        };
+        let rest = &line[1..];
+        match name.spelling.as_str() {
+            "if" | "ifdef" | "ifndef" => {
+                let value = active
+                    && match name.spelling.as_str() {
+                        "if" => self.condition(rest),
+                        "ifdef" => rest
+                            .first()
+                            .is_some_and(|n| self.macros.contains_key(&n.spelling)),
+                        _ => !rest
+                            .first()
+                            .is_some_and(|n| self.macros.contains_key(&n.spelling)),
+                    };
+                // a branch of an inactive conditional is never taken
+                conditions.push((value, value || !active));
+            }
+            "elif" | "elifdef" | "elifndef" => {
+                if let Some(&(_, taken)) = conditions.last() {
+                    let value = !taken
+                        && match name.spelling.as_str() {
+                            "elif" => self.condition(rest),
+                            "elifdef" => rest
+                                .first()
+                                .is_some_and(|n| self.macros.contains_key(&n.spelling)),
+                            _ => !rest
+                                .first()
+                                .is_some_and(|n| self.macros.contains_key(&n.spelling)),
+                        };
+                    *conditions.last_mut().unwrap() = (value, taken || value);
+                }
+            }
+            "else" => {
+                if let Some(last) = conditions.last_mut() {
+                    *last = (!last.1, true);
+                }
+            }
+            "endif" => {
+                conditions.pop();
+            }
+            _ if !active => {}
+            "define" => self.define(rest),
+            "undef" => {
+                if let Some(name) = rest.first() {
+                    self.macros.remove(&name.spelling);
+                }
+            }
+            "include" | "include_next" | "import" => self.include(rest, at, path, output)?,
+            "pragma" if rest.first().is_some_and(|t| t.spelling == "once") => {
+                if let Some(path) = path {
+                    self.once
+                        .insert(path.canonicalize().unwrap_or(path.to_path_buf()));
+                }
+            }
+            // #error, #warning, #line and unknown directives
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, rest: &[Token]) {
+        let Some(name) = rest.first() else {
+            return;
+        };
+        let mut params = None;
+        let mut variadic = false;
+        let mut body_start = 1;
+        // function-like if `(` follows the name without a space
+        if let Some(open) = rest.get(1) {
+            if open.spelling == "("
+                && open.line == name.line
+                && open.column == name.column + name.spelling.chars().count() as u32
+            {
+                let mut list = vec![];
+                let mut j = 2;
+                while j < rest.len() && rest[j].spelling != ")" {
+                    match rest[j].spelling.as_str() {
+                        "," => {}
+                        "..." => {
+                            variadic = true;
+                            // GNU named variadic parameters are written `args...`
+                            if rest[j - 1].spelling == "," || j == 2 {
+                                list.push("__VA_ARGS__".to_string());
+                            }
+                        }
+                        param => list.push(param.to_string()),
+                    }
+                    j += 1;
+                }
+                params = Some(list);
+                body_start = j + 1;
+            }
+        }
+        let body = rest.get(body_start..).unwrap_or_default().to_vec();
+        self.macros.insert(
+            name.spelling.clone(),
+            Macro {
+                params,
+                variadic,
+                body,
+            },
+        );
+    }
+
+    fn include(
+        &mut self,
+        rest: &[Token],
+        at: &Token,
+        path: Option<&Path>,
+        output: Option<&mut Vec<Token>>,
+    ) -> anyhow::Result<()> {
+        let mut rest = rest.to_vec();
+        // computed includes
+        if rest
+            .first()
+            .is_some_and(|t| t.kind != 1 && t.spelling != "<")
+        {
+            rest = self.expand(rest);
+        }
+        let Some(first) = rest.first() else {
+            return Ok(());
+        };
+        let (name, quoted) = if first.spelling.starts_with('"') {
+            (first.spelling.trim_matches('"').to_string(), true)
+        } else if first.spelling == "<" {
+            let name: String = rest[1..]
+                .iter()
+                .take_while(|t| t.spelling != ">")
+                .map(|t| t.spelling.as_str())
+                .collect();
+            (name, false)
+        } else {
+            return Ok(());
+        };
+
+        let mut candidates = vec![];
+        if quoted {
+            if let Some(dir) = path.and_then(|p| p.parent()) {
+                candidates.push(dir.join(&name));
+            }
+        }
+        candidates.extend(self.options.include_dirs.iter().map(|dir| dir.join(&name)));
+        // system headers are not needed to see through the student's macros
+        let Some(found) = candidates.into_iter().find(|p| p.is_file()) else {
+            return Ok(());
+        };
+        let canonical = found.canonicalize().unwrap_or(found.clone());
+        if self.once.contains(&canonical) || self.depth >= MAX_INCLUDE_DEPTH {
+            return Ok(());
+        }
+        let (content, _) = decode::read(&found)?;
+        let own = quoted
+            && self
+                .root
+                .as_ref()
+                .is_some_and(|root| canonical.starts_with(root));
+        self.depth += 1;
+        let res = match output {
+            // the student's own headers are part of the file
+            Some(output) if own => {
+                let mut tokens = vec![];
+                let res = self.file(&content, Some(&found), Some(&mut tokens));
+                for mut token in tokens {
+                    locate(&mut token, at);
+                    output.push(token);
+                }
+                res
+            }
+            _ => self.file(&content, Some(&found), None),
+        };
+        self.depth -= 1;
+        res
+    }
+
+    // value of an #if or #elif expression
+    fn condition(&mut self, rest: &[Token]) -> bool {
+        // `defined` is evaluated before macro expansion
+        let mut tokens = vec![];
+        let mut k = 0;
+        while k < rest.len() {
+            if rest[k].spelling == "defined" {
+                let (name, next) = if rest.get(k + 1).is_some_and(|t| t.spelling == "(") {
+                    (rest.get(k + 2), k + 4)
+                } else {
+                    (rest.get(k + 1), k + 2)
+                };
+                let value = name.is_some_and(|n| self.macros.contains_key(&n.spelling));
+                tokens.push(literal((value as u8).to_string(), &rest[k]));
+                k = next;
+                continue;
+            }
+            tokens.push(rest[k].clone());
+            k += 1;
+        }
+        let tokens = self.expand(tokens);
+        let mut expression = Expression {
+            tokens: &tokens,
+            pos: 0,
+            dialect: self.dialect,
+        };
+        expression.conditional() != 0
+    }
+
+    fn expand(&mut self, tokens: Vec<Token>) -> Vec<Token> {
+        let input = tokens
+            .into_iter()
+            .map(|token| Pending {
+                token,
+                hide: vec![],
+            })
+            .collect();
+        self.expand_pending(input)
+            .into_iter()
+            .map(|p| p.token)
+            .collect()
+    }
+
+    fn expand_pending(&mut self, mut input: VecDeque<Pending>) -> Vec<Pending> {
+        let mut res = vec![];
+        while let Some(current) = input.pop_front() {
+            let name = current.token.spelling.clone();
+            if !is_identifier(&name) || current.hide.contains(&name) {
+                res.push(current);
+                continue;
+            }
+            if name == "__LINE__" || name == "__FILE__" {
+                let spelling = if name == "__LINE__" {
+                    current.token.line.to_string()
+                } else {
+                    "\"\"".to_string()
+                };
+                res.push(Pending {
+                    token: literal(spelling, &current.token),
+                    hide: current.hide,
+                });
+                continue;
+            }
+            let Some(is_function) = self.macros.get(&name).map(|m| m.params.is_some()) else {
+                res.push(current);
+                continue;
+            };
+            if self.expansions >= MAX_EXPANSIONS
+                || (is_function && input.front().map(|p| p.token.spelling.as_str()) != Some("("))
+            {
+                res.push(current);
+                continue;
+            }
+            self.expansions += 1;
+
+            // the invocation, up to the closing parenthesis
+            let (args, at) = if is_function {
+                let (args, close) = collect_args(&mut input);
+                let at = match close {
+                    Some(close) => span(&current.token, &close),
+                    None => current.token.clone(),
+                };
+                (args, at)
+            } else {
+                (vec![], current.token.clone())
+            };
+            let mut hide = current.hide.clone();
+            hide.push(name.clone());
+            let body = self.substitute(&name, args, &hide, &at);
+            // rescan together with the rest of the input
+            for p in body.into_iter().rev() {
+                input.push_front(p);
+            }
+        }
+        res
+    }
+
+    // replace parameters in the body of macro `name`, applying `#` and `##`
+    fn substitute(
+        &mut self,
+        name: &str,
+        mut args: Vec<Vec<Pending>>,
+        hide: &[String],
+        at: &Token,
+    ) -> Vec<Pending> {
+        let (params, variadic, body) = {
+            let m = &self.macros[name];
+            (
+                m.params.clone().unwrap_or_default(),
+                m.variadic,
+                m.body.clone(),
+            )
+        };
+        // extra arguments belong to the variadic parameter
+        if variadic && !params.is_empty() && args.len() > params.len() {
+            let extra = args.split_off(params.len() - 1);
+            let mut joined = vec![];
+            for (index, arg) in extra.into_iter().enumerate() {
+                if index > 0 {
+                    joined.push(Pending {
+                        token: literal(",".to_string(), at),
+                        hide: vec![],
+                    });
+                }
+                joined.extend(arg);
+            }
+            args.push(joined);
+        }
+        let arg_of = |token: &Token| {
+            params
+                .iter()
+                .position(|p| *p == token.spelling)
+                .map(|index| args.get(index).cloned().unwrap_or_default())
+        };
+
+        let mut res: Vec<Pending> = vec![];
+        let mut k = 0;
+        while k < body.len() {
+            let token = &body[k];
+            if is_hash(token) && !params.is_empty() {
+                if let Some(arg) = body.get(k + 1).and_then(arg_of) {
+                    let text: Vec<String> = arg.iter().map(|p| p.token.spelling.clone()).collect();
+                    let escaped = text.join(" ").replace('\\', "\\\\").replace('"', "\\\"");
+                    res.push(Pending {
+                        token: literal(format!("\"{}\"", escaped), at),
+                        hide: vec![],
+                    });
+                    k += 2;
+                    continue;
+                }
+            }
+            if is_paste(token) {
+                let right = match body.get(k + 1) {
+                    Some(next) => arg_of(next).unwrap_or_else(|| {
+                        vec![Pending {
+                            token: next.clone(),
+                            hide: vec![],
+                        }]
+                    }),
+                    None => vec![],
+                };
+                k += 2;
+                match (res.pop(), right.first()) {
+                    (Some(left), Some(first)) => {
+                        let text = format!("{}{}", left.token.spelling, first.token.spelling);
+                        for (token, _) in lex(&text, self.dialect) {
+                            res.push(Pending {
+                                token,
+                                hide: vec![],
+                            });
+                        }
+                        res.extend(right.into_iter().skip(1));
+                    }
+                    // GNU `, ## __VA_ARGS__` drops the comma for no arguments
+                    (Some(left), None) if left.token.spelling == "," => {}
+                    (left, _) => {
+                        res.extend(left);
+                        res.extend(right);
+                    }
+                }
+                continue;
+            }
+            if let Some(arg) = arg_of(token) {
+                // operands of `##` are not expanded
+                if body.get(k + 1).is_some_and(is_paste) {
+                    res.extend(arg);
+                } else {
+                    res.extend(self.expand_pending(arg.into()));
+                }
+                k += 1;
+                continue;
+            }
+            res.push(Pending {
+                token: token.clone(),
+                hide: vec![],
+            });
+            k += 1;
+        }
+
+        for p in &mut res {
+            p.hide.extend(hide.iter().cloned());
+            locate(&mut p.token, at);
+        }
+        res
+    }
+}
+
+// arguments of a function-like macro invocation, starting at `(`, and the
+// closing `)` unless the input ends before it
+fn collect_args(input: &mut VecDeque<Pending>) -> (Vec<Vec<Pending>>, Option<Token>) {
+    input.pop_front();
+    let mut args = vec![vec![]];
+    let mut close = None;
+    let mut depth = 0;
+    while let Some(p) = input.pop_front() {
+        match p.token.spelling.as_str() {
+            ")" if depth == 0 => {
+                close = Some(p.token);
+                break;
+            }
+            "," if depth == 0 => {
+                args.push(vec![]);
+                continue;
+            }
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            _ => {}
+        }
+        args.last_mut().unwrap().push(p);
+    }
+    // `F()` has no arguments
+    if args.len() == 1 && args[0].is_empty() {
+        args.clear();
+    }
+    (args, close)
+}
+
+// integer constant expression of #if
+struct Expression<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    dialect: Dialect,
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "*" | "/" | "%" => 10,
+        "+" | "-" => 9,
+        "<<" | ">>" => 8,
+        "<" | "<=" | ">" | ">=" => 7,
+        "==" | "!=" | "not_eq" => 6,
+        "&" | "bitand" => 5,
+        "^" | "xor" => 4,
+        "|" | "bitor" => 3,
+        "&&" | "and" => 2,
+        "||" | "or" => 1,
+        _ => return None,
+    })
+}
+
+fn number(spelling: &str) -> i64 {
+    if let Some(start) = spelling
+        .find('\'')
+        .filter(|_| !spelling.starts_with(|c: char| c.is_ascii_digit()))
+    {
+        // character literal
+        let mut chars = spelling[start + 1..].chars();
+        return match chars.next() {
+            Some('\\') => match chars.next() {
+                Some('n') => 10,
+                Some('t') => 9,
+                Some('r') => 13,
+                Some('0') => 0,
+                Some(c) => c as i64,
+                None => 0,
+            },
+            Some(c) => c as i64,
+            None => 0,
+        };
+    }
+    let digits = spelling.replace('\'', "").to_ascii_lowercase();
+    let digits = digits.trim_end_matches(['u', 'l', 'z']);
+    let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        u64::from_str_radix(binary, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    parsed.unwrap_or(0) as i64
+}
+
+impl Expression<'_> {
+    fn peek(&self) -> &str {
+        self.tokens
+            .get(self.pos)
+            .map_or("", |t| t.spelling.as_str())
+    }
+
+    fn conditional(&mut self) -> i64 {
+        let condition = self.binary(1);
+        if self.peek() != "?" {
+            return condition;
+        }
+        self.pos += 1;
+        let left = self.conditional();
+        if self.peek() == ":" {
+            self.pos += 1;
+        }
+        let right = self.conditional();
+        if condition != 0 {
+            left
+        } else {
+            right
+        }
+    }
+
+    fn binary(&mut self, min: u8) -> i64 {
+        let mut left = self.unary();
+        while let Some(prec) = precedence(self.peek()).filter(|prec| *prec >= min) {
+            let op = self.peek().to_string();
+            self.pos += 1;
+            let right = self.binary(prec + 1);
+            left = match op.as_str() {
+                "*" => left.wrapping_mul(right),
+                "/" => left.checked_div(right).unwrap_or(0),
+                "%" => left.checked_rem(right).unwrap_or(0),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "<" => (left < right) as i64,
+                "<=" => (left <= right) as i64,
+                ">" => (left > right) as i64,
+                ">=" => (left >= right) as i64,
+                "==" => (left == right) as i64,
+                "!=" | "not_eq" => (left != right) as i64,
+                "&" | "bitand" => left & right,
+                "^" | "xor" => left ^ right,
+                "|" | "bitor" => left | right,
+                "&&" | "and" => (left != 0 && right != 0) as i64,
+                _ => (left != 0 || right != 0) as i64,
+            };
+        }
+        left
+    }
+
+    fn unary(&mut self) -> i64 {
+        let Some(token) = self.tokens.get(self.pos) else {
+            return 0;
+        };
+        self.pos += 1;
+        match token.spelling.as_str() {
+            "!" | "not" => (self.unary() == 0) as i64,
+            "~" | "compl" => !self.unary(),
+            "-" => self.unary().wrapping_neg(),
+            "+" => self.unary(),
+            "(" => {
+                let value = self.conditional();
+                if self.peek() == ")" {
+                    self.pos += 1;
+                }
+                value
+            }
+            "true" if self.dialect == Dialect::Cpp => 1,
+            // remaining identifiers are zero
+            _ if token.kind == 1 => number(&token.spelling),
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, Options};
+    use crate::lang::tokenizer::cpp::{lexer, Dialect};
+    use crate::token::Kind;
+    use std::path::Path;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "#define LOOP for\n#define REP(i, n) LOOP (int i = 0; i < n; i++)\nint main() {\n  REP(k, 10) {}\n}";
+        let tokens = tokenize(code, None, Dialect::Cpp, &Options::default()).unwrap();
+        let expected = lexer::tokenize_str(
+            "int main() {\n  for (int k = 0; k < 10; k++) {}\n}",
+            Dialect::Cpp,
+        );
+
+        eprintln!("{:?}", tokens);
+
+        let kinds: Vec<Kind> = tokens.iter().map(|t| t.kind).collect();
+        let expected_kinds: Vec<Kind> = expected.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, expected_kinds);
+
+        // expanded tokens are located at the invocation
+        assert_eq!(tokens[5].spelling, "for");
+        assert_eq!(tokens[5].line, 4);
+        assert_eq!(tokens[5].column, 3);
+        assert_eq!(tokens[7].spelling, "int");
+        assert_eq!(tokens[7].line, 4);
+        // up to the closing parenthesis of `REP(k, 10)`
+        assert_eq!(tokens[7].end_line, 4);
+        assert_eq!(tokens[7].end_column, 13);
+        assert_eq!(tokens[7].bytes, 79..89);
+
+        assert_eq!(tokens[21].spelling, "}");
+        assert_eq!(tokens[21].line, 5);
+        assert_eq!(tokens[21].column, 1);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let code = "#if defined(FAST) && VERSION >= 2\nfast();\n#elif 0\nnever();\n#else\nslow();\n#endif\n#define CAT(a, b) a ## b\n#define STR(x) #x\nCAT(x, 1) = STR(y z);";
+        let options = Options::from_args(&["-DFAST", "-D", "VERSION=3"], Path::new("."));
+        let tokens = tokenize(code, None, Dialect::C, &options).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        let spellings: Vec<&str> = tokens.iter().map(|t| t.spelling.as_str()).collect();
+        assert_eq!(
+            spellings,
+            vec!["fast", "(", ")", ";", "x1", "=", "\"y z\"", ";"]
+        );
+        assert_eq!(tokens[0].line, 2);
+        assert_eq!(tokens[4].line, 10);
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("jieplag-preprocess-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("include")).unwrap();
+        std::fs::write(
+            dir.join("include").join("defs.h"),
+            "#pragma once\n#define WHILE while\nint hidden;",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("compile_commands.json"),
+            r#"[{"directory": ".", "file": "main.c", "command": "cc -I include -DN=\"2\" -c main.c"}]"#
+                .replace("\".\"", &format!("{:?}", dir.display().to_string())),
+        )
+        .unwrap();
+        std::fs::write(dir.join("own.h"), "#define ONE 1\nint own = ONE;").unwrap();
+        let main = dir.join("main.c");
+        std::fs::write(
+            &main,
+            "#include <defs.h>\n#include \"include/defs.h\"\n#include \"own.h\"\nWHILE (N) {}",
+        )
+        .unwrap();
+
+        let options =
+            Options::from_compile_commands(&dir.join("compile_commands.json"), &main, None)
+                .unwrap();
+        let content = std::fs::read_to_string(&main).unwrap();
+        let tokens = tokenize(&content, Some(&main), Dialect::C, &options).unwrap();
+
+        // another submission takes the entry of the same relative path
+        let submission = dir.join("submissions").join("alice");
+        std::fs::create_dir_all(&submission).unwrap();
+        std::fs::write(submission.join("main.c"), "").unwrap();
+        let database = dir.join("compile_commands.json");
+        assert!(
+            Options::from_compile_commands(&database, &submission.join("main.c"), None).is_err()
+        );
+        let found = Options::from_compile_commands(
+            &database,
+            &submission.join("main.c"),
+            Some(&submission),
+        )
+        .unwrap();
+        assert_eq!(found.include_dirs, options.include_dirs);
+        assert_eq!(found.defines, options.defines);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        // tokens of headers are only kept for the student's own, included
+        // with quotes, and located at the `#include`
+        let spellings: Vec<&str> = tokens.iter().map(|t| t.spelling.as_str()).collect();
+        assert_eq!(
+            spellings,
+            vec!["int", "own", "=", "1", ";", "while", "(", "2", ")", "{", "}"]
+        );
+        assert_eq!(tokens[1].line, 3);
+        assert_eq!(tokens[1].column, 1);
+        assert_eq!(tokens[1].end_column, 17);
+        assert_eq!(tokens[5].line, 4);
+    }
+}
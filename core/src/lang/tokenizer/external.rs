@@ -0,0 +1,334 @@
+//! Tokens from an external command, for languages without a Rust lexer, e.g.
+//! Racket, Prolog or Coq
+//!
+//! The command gets the source on stdin and prints a JSON array of tokens,
+//! e.g. `[{"kind": "identifier", "spelling": "x", "line": 1, "column": 1}]`,
+//! with 1-based lines and character columns, and optionally `end_line` and
+//! `end_column` just past the token. Kinds are any string or number, hashed
+//! into jieplag's kinds. To report what it could not lex, the command may
+//! print `{"tokens": [...], "diagnostics": [{"line": 1, "column": 1,
+//! "message": "..."}]}` instead.
+use super::fallback::{self, Syntax};
+use super::scanner::LineIndex;
+use crate::common::{split_command, stable_hash};
+use crate::lang::{register, Language, Tokenize};
+use crate::token::{Diagnostic, Kind, Tokenized};
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub struct External {
+    /// program, then its arguments
+    pub command: Vec<String>,
+    /// the command is killed if it takes longer
+    pub timeout: Duration,
+}
+
+// nothing is known about the language if the command fails
+const SYNTAX: Syntax = Syntax {
+    line_comments: &[],
+    block_comments: &[],
+    quotes: &['"'],
+};
+
+#[derive(Deserialize)]
+struct ExternalToken {
+    kind: Value,
+    spelling: String,
+    line: u32,
+    column: u32,
+    end_line: Option<u32>,
+    end_column: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ExternalDiagnostic {
+    line: u32,
+    column: u32,
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Output {
+    Tokens(Vec<ExternalToken>),
+    Tokenized {
+        tokens: Vec<ExternalToken>,
+        #[serde(default)]
+        diagnostics: Vec<ExternalDiagnostic>,
+    },
+}
+
+// hashed, so that kinds agree across files and runs, below those of fallback
+// tokens
+fn kind(kind: &Value) -> anyhow::Result<Kind> {
+    let text = match kind {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        other => return Err(anyhow!("Invalid token kind: {}", other)),
+    };
+    Ok((stable_hash(text.as_bytes()) % fallback::FIRST_KIND as u64) as Kind)
+}
+
+impl External {
+    pub fn new(command: Vec<String>) -> Self {
+        Self {
+            command,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    // stdout of the command, failing if it fails or times out
+    fn run(&self, content: &str) -> anyhow::Result<Vec<u8>> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow!("Empty tokenizer command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", program))?;
+
+        // in threads, so that neither side blocks on a full pipe
+        let mut stdin = child.stdin.take().unwrap();
+        let input = content.to_string();
+        // the command may exit without reading everything
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let mut stdout = child.stdout.take().unwrap();
+        let (stdout_sender, stdout_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = vec![];
+            stdout_sender.send(stdout.read_to_end(&mut buf).map(|_| buf))
+        });
+        let mut stderr = child.stderr.take().unwrap();
+        let (stderr_sender, stderr_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = String::new();
+            stderr_sender.send(stderr.read_to_string(&mut buf).map(|_| buf))
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let timed_out = || anyhow!("{} timed out after {:?}", program, self.timeout);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                return Err(timed_out());
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        // the pipes may outlive the command, e.g. held open by its children
+        if !status.success() {
+            let stderr = receive(&stderr_receiver, deadline).ok_or_else(timed_out)?;
+            return Err(anyhow!(
+                "{} failed with {}: {}",
+                program,
+                status,
+                stderr.unwrap_or_default().trim()
+            ));
+        }
+        Ok(receive(&stdout_receiver, deadline).ok_or_else(timed_out)??)
+    }
+
+    /// Tokens as the command gives them, failing if it fails
+    pub fn tokenize_strict(&self, content: &str) -> anyhow::Result<Tokenized> {
+        let stdout = self.run(content)?;
+        let (tokens, diagnostics) = match serde_json::from_slice(&stdout)
+            .with_context(|| format!("Invalid tokens from {}", self.command[0]))?
+        {
+            Output::Tokens(tokens) => (tokens, vec![]),
+            Output::Tokenized {
+                tokens,
+                diagnostics,
+            } => (tokens, diagnostics),
+        };
+
+        let lines = LineIndex::new(content);
+        let mut res = Tokenized::default();
+        for token in tokens {
+            let start = lines.byte(token.line, token.column);
+            let end = match (token.end_line, token.end_column) {
+                (Some(line), Some(column)) => lines.byte(line, column),
+                _ if content[start..].starts_with(&token.spelling) => start + token.spelling.len(),
+                _ => start,
+            };
+            res.tokens
+                .push(lines.token(kind(&token.kind)?, token.spelling, start..end.max(start)));
+        }
+        res.diagnostics = diagnostics
+            .into_iter()
+            .map(|d| Diagnostic {
+                line: d.line,
+                column: d.column,
+                message: d.message,
+            })
+            .collect();
+        Ok(res)
+    }
+}
+
+// what a reader thread sends by the deadline
+fn receive<T>(receiver: &Receiver<T>, deadline: Instant) -> Option<T> {
+    receiver
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+impl Tokenize for External {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        self.tokenize_strict(content)
+            .unwrap_or_else(|err| fallback::tokenize(content, &SYNTAX, err))
+    }
+}
+
+/// An external tokenizer for a language of its own, given as
+/// `NAME:EXTENSIONS:COMMAND`, e.g. `racket:rkt,scrbl:racket lex.rkt`, where
+/// the command is split into words as by a shell, see `split_command`
+#[derive(Clone, Debug)]
+pub struct Plugin {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub tokenizer: External,
+}
+
+impl FromStr for Plugin {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(name), Some(extensions), Some(command)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow!("Expected NAME:EXTENSIONS:COMMAND, got {:?}", s));
+        };
+        let command = split_command(command);
+        if name.is_empty() || command.is_empty() {
+            return Err(anyhow!("Expected NAME:EXTENSIONS:COMMAND, got {:?}", s));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            extensions: extensions
+                .split(',')
+                .filter(|e| !e.is_empty())
+                .map(|e| e.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            tokenizer: External::new(command),
+        })
+    }
+}
+
+impl Plugin {
+    /// Register as a language, see `lang::register`; plugins live as long as
+    /// the process
+    pub fn register(self) -> anyhow::Result<Language> {
+        let extensions: Vec<&'static str> =
+            self.extensions.into_iter().map(|e| &*e.leak()).collect();
+        register(self.name.leak(), 2, &extensions, self.tokenizer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{External, Plugin};
+    use crate::lang::{tokenize_str, Language, Tokenize};
+    use std::time::Duration;
+
+    fn sh(script: &str) -> External {
+        External::new(vec!["sh".into(), "-c".into(), script.into()])
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokenizer = sh(r#"cat > /dev/null; echo '[
+            {"kind": "paren", "spelling": "(", "line": 1, "column": 1},
+            {"kind": "symbol", "spelling": "λ", "line": 1, "column": 2},
+            {"kind": 7, "spelling": "x", "line": 2, "column": 3, "end_line": 2, "end_column": 4}
+        ]'"#);
+        let tokenized = tokenizer.tokenize_str("(λ\n  x)");
+
+        eprintln!("{:?}", tokenized);
+
+        assert!(tokenized.diagnostics.is_empty());
+        let tokens = tokenized.tokens;
+        assert_eq!(tokens[1].spelling, "λ");
+        assert_eq!(tokens[1].column, 2);
+        assert_eq!(tokens[1].end_column, 3);
+        assert_eq!(tokens[1].bytes, 1..3);
+        assert_eq!(tokens[2].line, 2);
+        assert_eq!(tokens[2].column, 3);
+        assert_eq!(tokens[2].bytes, 6..7);
+        assert_ne!(tokens[0].kind, tokens[1].kind);
+
+        // kinds are the same in every run
+        let again = tokenizer.tokenize_str("(λ\n  x)").tokens;
+        assert_eq!(tokens[2].kind, again[2].kind);
+    }
+
+    #[test]
+    fn test_failure() {
+        // diagnostics of the command
+        let tokenized = sh(r#"cat > /dev/null; echo '{"tokens": [], "diagnostics": [
+            {"line": 1, "column": 2, "message": "unexpected"}
+        ]}'"#)
+        .tokenize_str("a b");
+        assert_eq!(tokenized.diagnostics[0].column, 2);
+
+        // otherwise, the fallback lexer
+        let tokenized = sh("echo oops >&2; exit 3").tokenize_str("a b");
+        assert_eq!(tokenized.tokens.len(), 2);
+        assert!(tokenized.diagnostics[0].message.contains("oops"));
+
+        let tokenized = sh("echo '[{]'").tokenize_str("a b");
+        assert!(tokenized.diagnostics[0].message.contains("Invalid tokens"));
+
+        let mut slow = sh("sleep 5");
+        slow.timeout = Duration::from_millis(100);
+        let tokenized = slow.tokenize_str("a b");
+        assert_eq!(tokenized.tokens.len(), 2);
+        assert!(tokenized.diagnostics[0].message.contains("timed out"));
+
+        // a child still holding stdout
+        let mut lingering = sh("cat > /dev/null; sleep 5 & echo []");
+        lingering.timeout = Duration::from_millis(500);
+        let start = std::time::Instant::now();
+        let tokenized = lingering.tokenize_str("a b");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(tokenized.diagnostics[0].message.contains("timed out"));
+
+        let tokenized = External::new(vec!["/nonexistent".into()]).tokenize_str("a b");
+        assert_eq!(tokenized.tokens.len(), 2);
+    }
+
+    #[test]
+    fn test_plugin() {
+        let plugin: Plugin = "shwords:SHW,.shw2:sh -c tr\t-s".parse().unwrap();
+        assert_eq!(plugin.extensions, ["shw", "shw2"]);
+        assert_eq!(plugin.tokenizer.command, ["sh", "-c", "tr", "-s"]);
+        assert!("racket:rkt".parse::<Plugin>().is_err());
+        assert!(":rkt:racket".parse::<Plugin>().is_err());
+        let plugin: Plugin = r#"quoted:q:sh -c "tr -s ' '""#.parse().unwrap();
+        assert_eq!(plugin.tokenizer.command, ["sh", "-c", "tr -s ' '"]);
+
+        let plugin: Plugin = "shecho::printf []".parse().unwrap();
+        let language = plugin.register().unwrap();
+        assert_eq!(language, Language::Custom("shecho"));
+        assert!(tokenize_str("a", language).unwrap().tokens.is_empty());
+    }
+}
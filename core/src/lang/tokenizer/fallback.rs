@@ -0,0 +1,260 @@
+//! Lenient lexer for code that a language's own tokenizer rejects, so that
+//! half-finished files are still compared
+use super::scanner::Position;
+use crate::token::{Diagnostic, Kind, Token, Tokenized};
+
+/// Just enough of a language's lexical syntax to skip comments and strings
+pub struct Syntax {
+    pub line_comments: &'static [&'static str],
+    pub block_comments: &'static [(&'static str, &'static str)],
+    pub quotes: &'static [char],
+}
+
+/// Kinds from here up are reserved for fallback tokens, which `recover` mixes
+/// with a language's own: those must stay below, hashed ones included
+pub const FIRST_KIND: Kind = 0xF000;
+
+const WORD: Kind = FIRST_KIND;
+const NUMBER: Kind = FIRST_KIND + 1;
+const STRING: Kind = FIRST_KIND + 2;
+const PUNCTUATION: Kind = FIRST_KIND + 3;
+
+fn starts_with(chars: &[char], i: usize, text: &str) -> bool {
+    text.chars()
+        .enumerate()
+        .all(|(k, ch)| chars.get(i + k) == Some(&ch))
+}
+
+/// Words, numbers, strings and single punctuation characters, never failing
+pub fn lex(content: &str, syntax: &Syntax) -> Vec<Token> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut res = vec![];
+    let mut pos = Position::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let mut end = i + 1;
+        let mut kind = None;
+        if c.is_whitespace() {
+            // skipped
+        } else if let Some((open, close)) = syntax
+            .block_comments
+            .iter()
+            .find(|(open, _)| starts_with(&chars, i, open))
+        {
+            // before line comments, which may be prefixes, e.g. `--[[` in Lua
+            end = i + open.chars().count();
+            while end < chars.len() && !starts_with(&chars, end, close) {
+                end += 1;
+            }
+            end = (end + close.chars().count()).min(chars.len());
+        } else if syntax
+            .line_comments
+            .iter()
+            .any(|prefix| starts_with(&chars, i, prefix))
+        {
+            while end < chars.len() && chars[end] != '\n' {
+                end += 1;
+            }
+        } else if syntax.quotes.contains(&c) {
+            // up to the closing quote or the end of the line
+            while end < chars.len() && chars[end] != c && chars[end] != '\n' {
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            if end < chars.len() && chars[end] == c {
+                end += 1;
+            }
+            end = end.min(chars.len());
+            kind = Some(STRING);
+        } else if c.is_alphanumeric() || c == '_' {
+            while end < chars.len()
+                && (chars[end].is_alphanumeric() || chars[end] == '_' || chars[end] == '.')
+            {
+                if chars[end] == '.' && !c.is_ascii_digit() {
+                    break;
+                }
+                end += 1;
+            }
+            kind = Some(if c.is_ascii_digit() { NUMBER } else { WORD });
+        } else {
+            kind = Some(PUNCTUATION + (c as u32 % (Kind::MAX - PUNCTUATION + 1) as u32) as Kind);
+        }
+
+        let start = pos;
+        pos.advance(&chars[i..end]);
+        if let Some(kind) = kind {
+            res.push(start.token(&pos, kind, chars[i..end].iter().collect()));
+        }
+        i = end;
+    }
+    res
+}
+
+/// Where and why a tokenizer failed, at the start if it does not tell
+pub fn diagnostic(err: anyhow::Error) -> Diagnostic {
+    err.downcast().unwrap_or_else(|err| Diagnostic {
+        line: 1,
+        column: 1,
+        message: err.to_string(),
+    })
+}
+
+/// Tokenize the whole file with the fallback lexer, reporting why the
+/// language's own tokenizer failed
+pub fn tokenize(content: &str, syntax: &Syntax, err: anyhow::Error) -> Tokenized {
+    Tokenized {
+        tokens: lex(content, syntax),
+        diagnostics: vec![diagnostic(err)],
+        ..Default::default()
+    }
+}
+
+/// Tokenize with `strict`, taking the lines it fails on with the fallback
+/// lexer and retrying on the lines before and after. Errors that are a
+/// `Diagnostic` locate the failing line, other errors give up on the chunk.
+pub fn recover(
+    content: &str,
+    syntax: &Syntax,
+    strict: impl Fn(&str) -> anyhow::Result<Vec<Token>>,
+) -> Tokenized {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    // byte offsets of line starts
+    let mut starts = vec![0];
+    starts.extend(lines.iter().scan(0, |offset, line| {
+        *offset += line.len();
+        Some(*offset)
+    }));
+    let shift = |tokens: Vec<Token>, line: usize| {
+        let offset = starts[line];
+        tokens.into_iter().map(move |mut token| {
+            token.line += line as u32;
+            token.end_line += line as u32;
+            token.bytes = token.bytes.start + offset..token.bytes.end + offset;
+            token
+        })
+    };
+
+    let mut res = Tokenized::default();
+    // 0-based ranges of lines
+    let mut chunks = vec![(0, lines.len())];
+    while let Some((from, to)) = chunks.pop() {
+        let err = match strict(&lines[from..to].concat()) {
+            Ok(tokens) => {
+                res.tokens.extend(shift(tokens, from));
+                continue;
+            }
+            Err(err) => err,
+        };
+        match err.downcast::<Diagnostic>() {
+            Ok(mut diagnostic) if from < to => {
+                // clamped, e.g. for errors at the end of the file
+                let line = from + (diagnostic.line as usize).clamp(1, to - from) - 1;
+                diagnostic.line = line as u32 + 1;
+                res.diagnostics.push(diagnostic);
+                res.tokens.extend(shift(lex(lines[line], syntax), line));
+                for chunk in [(line + 1, to), (from, line)] {
+                    if chunk.0 < chunk.1 {
+                        chunks.push(chunk);
+                    }
+                }
+            }
+            Ok(diagnostic) => res.diagnostics.push(diagnostic),
+            Err(err) => {
+                res.diagnostics.push(Diagnostic {
+                    line: from as u32 + 1,
+                    column: 1,
+                    message: err.to_string(),
+                });
+                let chunk = lines[from..to].concat();
+                res.tokens.extend(shift(lex(&chunk, syntax), from));
+            }
+        }
+    }
+    res.tokens.sort_by_key(|token| (token.line, token.column));
+    res.diagnostics
+        .sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lex, recover, Syntax, FIRST_KIND};
+    use crate::token::{Diagnostic, Token};
+
+    const SYNTAX: Syntax = Syntax {
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        quotes: &['"'],
+    };
+
+    #[test]
+    fn test_lex() {
+        let code = "x = 1.5; // comment\ny(\"a\\\"b\") /* block */ + z_2";
+        let tokens = lex(code, &SYNTAX);
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "x");
+        assert_eq!(tokens[2].spelling, "1.5");
+        assert_eq!(tokens[3].spelling, ";");
+
+        assert_eq!(tokens[4].spelling, "y");
+        assert_eq!(tokens[4].line, 2);
+        assert_eq!(tokens[4].column, 1);
+        assert_eq!(tokens[6].spelling, "\"a\\\"b\"");
+        assert_eq!(tokens[6].end_column, 9);
+        assert_eq!(tokens[6].bytes, 22..28);
+        assert_eq!(tokens[8].spelling, "+");
+        assert_eq!(tokens[8].column, 23);
+        assert_eq!(tokens[9].spelling, "z_2");
+    }
+
+    #[test]
+    fn test_recover() {
+        // a strict lexer rejecting `@`
+        let strict = |content: &str| -> anyhow::Result<Vec<Token>> {
+            for (index, line) in content.lines().enumerate() {
+                if let Some(column) = line.find('@') {
+                    return Err(Diagnostic {
+                        line: index as u32 + 1,
+                        column: column as u32 + 1,
+                        message: "unexpected `@`".to_string(),
+                    }
+                    .into());
+                }
+            }
+            Ok(lex(content, &SYNTAX)
+                .into_iter()
+                .map(|mut token| {
+                    token.kind = token.kind - FIRST_KIND + 100;
+                    token
+                })
+                .collect())
+        };
+        let tokenized = recover("a\nb @ c\nd\ne @\nf", &SYNTAX, strict);
+
+        eprintln!("{:?}", tokenized);
+
+        let spellings: Vec<&str> = tokenized
+            .tokens
+            .iter()
+            .map(|t| t.spelling.as_str())
+            .collect();
+        assert_eq!(spellings, ["a", "b", "@", "c", "d", "e", "@", "f"]);
+        // only the failing lines are taken by the fallback lexer
+        assert_eq!(tokenized.tokens[0].kind, 100);
+        assert_eq!(tokenized.tokens[1].kind, FIRST_KIND);
+        assert_eq!(tokenized.tokens[4].kind, 100);
+        assert_eq!(tokenized.tokens[4].line, 3);
+        assert_eq!(tokenized.tokens[7].kind, 100);
+        assert_eq!(tokenized.tokens[7].line, 5);
+        assert_eq!(tokenized.tokens[7].bytes, 14..15);
+        assert_eq!(tokenized.tokens[6].end_line, 4);
+        assert_eq!(tokenized.tokens[6].end_column, 4);
+
+        assert_eq!(tokenized.diagnostics.len(), 2);
+        assert_eq!(tokenized.diagnostics[0].line, 2);
+        assert_eq!(tokenized.diagnostics[0].column, 3);
+        assert_eq!(tokenized.diagnostics[1].line, 4);
+    }
+}
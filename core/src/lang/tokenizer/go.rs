@@ -0,0 +1,96 @@
+use super::{fallback::Syntax, grammar::Grammar};
+use crate::lang::Tokenize;
+use crate::token::{Token, Tokenized};
+
+pub struct Go;
+
+impl Tokenize for Go {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        GRAMMAR.tokenize_str(content)
+    }
+}
+
+pub static GRAMMAR: Grammar = Grammar::new(
+    "Go",
+    tree_sitter_go::language,
+    // statement terminators, explicit or inserted, are dropped too
+    &["comment", ";", "\n", "\0"],
+    &[
+        "identifier",
+        "field_identifier",
+        "package_identifier",
+        "type_identifier",
+        "label_name",
+    ],
+    &[
+        &["int_literal", "float_literal", "imaginary_literal"],
+        &[
+            "interpreted_string_literal",
+            "raw_string_literal",
+            "rune_literal",
+        ],
+    ],
+    Syntax {
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        quotes: &['"', '\'', '`'],
+    },
+);
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    GRAMMAR.tokenize_strict(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize_str;
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "package main\n\nimport \"fmt\"\n\n// entry\nfunc main() {\n\tfmt.Println(\"Hello, \\\"world\\\"\");\n}\n";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "package");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[1].spelling, "main");
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(tokens[1].column, 9);
+
+        assert_eq!(tokens[3].spelling, "\"fmt\"");
+        assert_eq!(tokens[3].line, 3);
+        assert_eq!(tokens[3].column, 8);
+
+        // comment is skipped
+        assert_eq!(tokens[4].spelling, "func");
+        assert_eq!(tokens[4].line, 6);
+        assert_eq!(tokens[4].column, 1);
+
+        // string literal is a single token
+        assert_eq!(tokens[13].spelling, "\"Hello, \\\"world\\\"\"");
+        assert_eq!(tokens[13].line, 7);
+        assert_eq!(tokens[13].column, 14);
+
+        // semicolon is skipped
+        assert_eq!(tokens[15].spelling, "}");
+        assert_eq!(tokens[15].line, 8);
+        assert_eq!(tokens[15].column, 1);
+    }
+
+    #[test]
+    fn test_rename() {
+        let left =
+            tokenize_str("package p\nfunc f() {\n\ta := 1\n\tfor a < 10 {\n\t\ta += 2\n\t}\n}\n")
+                .unwrap();
+        let right =
+            tokenize_str("package q\nfunc g() {\n\tcount := 3; for count < 42 { count += 5 }\n}\n")
+                .unwrap();
+        let left_kind: Vec<Kind> = left.iter().map(|t| t.kind).collect();
+        let right_kind: Vec<Kind> = right.iter().map(|t| t.kind).collect();
+        assert_eq!(left_kind, right_kind);
+    }
+}
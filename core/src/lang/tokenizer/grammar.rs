@@ -0,0 +1,277 @@
+//! Generic tokenizer for tree-sitter grammars, with kinds taken from the
+//! grammar's own symbol table
+use super::fallback::{self, Syntax};
+use super::scanner::LineIndex;
+use crate::lang::Tokenize;
+use crate::token::{Diagnostic, Kind, Token, Tokenized};
+use anyhow::anyhow;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::OnceLock;
+use tree_sitter::{Language, Node, Parser, Tree};
+
+/// A tree-sitter language, with the nodes that need special treatment
+pub struct Grammar {
+    pub name: &'static str,
+    pub language: fn() -> Language,
+    /// dropped nodes, named or not, e.g. comments or statement terminators
+    pub dropped: &'static [&'static str],
+    /// nodes sharing kind 0, taken as a single token
+    pub identifiers: &'static [&'static str],
+    /// groups of literal nodes sharing one kind each, taken as a single
+    /// token even if they have children, e.g. strings with escapes
+    pub literals: &'static [&'static [&'static str]],
+    /// for the fallback lexer
+    pub syntax: Syntax,
+    kinds: OnceLock<HashMap<(&'static str, bool), Kind>>,
+}
+
+impl Grammar {
+    pub const fn new(
+        name: &'static str,
+        language: fn() -> Language,
+        dropped: &'static [&'static str],
+        identifiers: &'static [&'static str],
+        literals: &'static [&'static [&'static str]],
+        syntax: Syntax,
+    ) -> Self {
+        Self {
+            name,
+            language,
+            dropped,
+            identifiers,
+            literals,
+            syntax,
+            kinds: OnceLock::new(),
+        }
+    }
+
+    // kinds of leaf nodes by name and namedness: terminal symbols in the
+    // order of the symbol table, then aliases
+    fn kinds(&self) -> &HashMap<(&'static str, bool), Kind> {
+        self.kinds.get_or_init(|| {
+            let language = (self.language)();
+
+            // terminals come before the root symbol, nonterminals after it;
+            // aliases follow and never appear as lookahead
+            let mut parser = Parser::new();
+            parser
+                .set_language(&language)
+                .expect("Error loading grammar");
+            let root = parser.parse("", None).unwrap().root_node().kind_id();
+            let mut lookahead = HashSet::new();
+            for state in 0..language.parse_state_count() as u16 {
+                if let Some(symbols) = language.lookahead_iterator(state) {
+                    lookahead.extend(symbols);
+                }
+            }
+
+            let mut res = HashMap::new();
+            let mut next = self.literals.len() + 1;
+            for id in 0..language.node_kind_count() as u16 {
+                let is_alias = id > root && !lookahead.contains(&id);
+                if !language.node_kind_is_visible(id) || !(id < root || is_alias) {
+                    continue;
+                }
+                let name = language.node_kind_for_id(id).unwrap();
+                let named = language.node_kind_is_named(id);
+                if self.dropped.contains(&name)
+                    || (named
+                        && (self.identifiers.contains(&name)
+                            || self.literals.iter().any(|group| group.contains(&name))))
+                {
+                    continue;
+                }
+                if let Entry::Vacant(entry) = res.entry((name, named)) {
+                    entry.insert(next as Kind);
+                    next += 1;
+                }
+            }
+            res
+        })
+    }
+
+    /// Number of kinds in use, including the one for unknown nodes; kinds
+    /// from this on are free for tokenizers to add their own
+    pub fn kind_count(&self) -> usize {
+        self.literals.len() + self.kinds().len() + 2
+    }
+
+    fn literal(&self, node: &Node) -> Option<Kind> {
+        if !node.is_named() {
+            return None;
+        }
+        self.literals
+            .iter()
+            .position(|group| group.contains(&node.kind()))
+            .map(|index| index as Kind + 1)
+    }
+
+    /// Whether a node is a single token, even if it has children: dropped
+    /// nodes, identifiers and literals are not descended
+    pub(crate) fn is_single(&self, node: &Node) -> bool {
+        self.dropped.contains(&node.kind())
+            || (node.is_named() && self.identifiers.contains(&node.kind()))
+            || self.literal(node).is_some()
+    }
+
+    /// Kind of a leaf or single node, `None` for dropped nodes
+    pub(crate) fn kind(&self, node: &Node) -> Option<Kind> {
+        let name = node.kind();
+        if self.dropped.contains(&name) {
+            None
+        } else if node.is_named() && self.identifiers.contains(&name) {
+            Some(0)
+        } else if let Some(kind) = self.literal(node) {
+            Some(kind)
+        } else {
+            // nodes missing from the symbol table, e.g. errors
+            let unknown = (self.kind_count() - 1) as Kind;
+            Some(
+                self.kinds()
+                    .get(&(name, node.is_named()))
+                    .copied()
+                    .unwrap_or(unknown),
+            )
+        }
+    }
+}
+
+impl Grammar {
+    pub(crate) fn parse(&self, content: &str) -> anyhow::Result<Tree> {
+        self.parse_as(content, &(self.language)())
+    }
+
+    /// Parse with a variant of the grammar sharing its node names, e.g. for
+    /// interface files
+    pub(crate) fn parse_as(&self, content: &str, language: &Language) -> anyhow::Result<Tree> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(language)
+            .map_err(|err| anyhow!("Error loading {} grammar: {}", self.name, err))?;
+        parser
+            .parse(content, None)
+            .ok_or_else(|| anyhow!("Failed to parse the code"))
+    }
+
+    pub(crate) fn tokenize_tree(&self, content: &str, tree: &Tree) -> Vec<Token> {
+        self.tokenize_tree_with(content, tree, |node, _| self.kind(node))
+    }
+
+    /// Tokens with kinds from `kind`, given each node and its text
+    pub(crate) fn tokenize_tree_with(
+        &self,
+        content: &str,
+        tree: &Tree,
+        kind: impl Fn(&Node, &str) -> Option<Kind>,
+    ) -> Vec<Token> {
+        let mut res = vec![];
+        let lines = LineIndex::new(content);
+        let root_node = tree.root_node();
+        // the root of an empty or blank source, which is no token
+        if root_node.child_count() == 0 {
+            return res;
+        }
+        let mut cursor = root_node.walk();
+        loop {
+            let node = cursor.node();
+            let is_single = self.is_single(&node);
+
+            if node.child_count() == 0 || is_single {
+                let text = &content[node.byte_range()];
+                if let Some(kind) = kind(&node, text) {
+                    res.push(lines.token(kind, text.to_string(), node.byte_range()));
+                }
+            }
+
+            if !is_single && cursor.goto_first_child() {
+                continue;
+            }
+
+            if cursor.goto_next_sibling() {
+                continue;
+            }
+
+            while cursor.goto_parent() && !cursor.goto_next_sibling() {}
+
+            if cursor.node() == tree.root_node() {
+                break;
+            }
+        }
+        res
+    }
+
+    /// Tokens, failing only if the grammar cannot be loaded
+    pub fn tokenize_strict(&self, content: &str) -> anyhow::Result<Vec<Token>> {
+        Ok(self.tokenize_tree(content, &self.parse(content)?))
+    }
+}
+
+impl Tokenize for Grammar {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        tokenized(content, &self.syntax, self.parse(content), |tree| {
+            self.tokenize_tree(content, tree)
+        })
+    }
+}
+
+/// Error and missing nodes, which tree-sitter has recovered from
+pub(crate) fn syntax_errors(content: &str, tree: &Tree) -> Vec<Diagnostic> {
+    let mut res = vec![];
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            let message = if node.is_missing() {
+                format!("missing `{}`", node.kind())
+            } else {
+                let text = content[node.byte_range()]
+                    .lines()
+                    .next()
+                    .unwrap_or_default();
+                format!("unexpected `{}`", text.chars().take(20).collect::<String>())
+            };
+            let start_position = node.start_position();
+            res.push(Diagnostic {
+                line: (start_position.row + 1) as u32,
+                column: (start_position.column + 1) as u32,
+                message,
+            });
+        }
+
+        // errors are reported once, at the outermost error node
+        if node.has_error() && !node.is_error() && cursor.goto_first_child() {
+            continue;
+        }
+
+        if cursor.goto_next_sibling() {
+            continue;
+        }
+
+        loop {
+            if !cursor.goto_parent() {
+                return res;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+}
+
+/// Tokens of a tree-sitter parse with its syntax errors, or of the fallback
+/// lexer if there is no parse
+pub(crate) fn tokenized(
+    content: &str,
+    syntax: &Syntax,
+    tree: anyhow::Result<Tree>,
+    tokenize_tree: impl FnOnce(&Tree) -> Vec<Token>,
+) -> Tokenized {
+    match tree {
+        Ok(tree) => Tokenized {
+            diagnostics: syntax_errors(content, &tree),
+            tokens: tokenize_tree(&tree),
+            ..Default::default()
+        },
+        Err(err) => fallback::tokenize(content, syntax, err),
+    }
+}
@@ -0,0 +1,441 @@
+use super::{
+    fallback::{recover, Syntax},
+    scanner::Position,
+};
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+
+pub struct Haskell;
+
+impl Tokenize for Haskell {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, tokenize_str)
+    }
+}
+
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("{-", "-}")],
+    quotes: &['"'],
+};
+
+// Haskell 2010 reserved identifiers
+const KEYWORDS: [&str; 23] = [
+    "case", "class", "data", "default", "deriving", "do", "else", "foreign", "if", "import", "in",
+    "infix", "infixl", "infixr", "instance", "let", "module", "newtype", "of", "then", "type",
+    "where", "_",
+];
+
+// reserved operators, special characters and common Prelude operators,
+// other operators share a single kind
+const SYMBOLS: [&str; 50] = [
+    "..", ":", "::", "=", "\\", "|", "<-", "->", "@", "~", "=>", "(", ")", ",", ";", "[", "]", "`",
+    "{", "}", "'", "+", "-", "*", "/", "^", "**", "++", ".", "$", "$!", "<$>", "<$", "<*>", "*>",
+    "<*", "<>", "<|>", ">>=", ">>", "=<<", "==", "/=", "<", "<=", ">", ">=", "&&", "||", "!!",
+];
+
+// kind: 0 for identifiers, [1, 3] for literals
+const NUMBER: Kind = 1;
+const CHARACTER: Kind = 2;
+const STRING: Kind = 3;
+// kind: [4, 6] for braces and semicolons implied by layout
+const LAYOUT_OPEN: Kind = 4;
+const LAYOUT_SEPARATOR: Kind = 5;
+const LAYOUT_CLOSE: Kind = 6;
+const OPERATOR: Kind = 7;
+// kind: [8, 8+KEYWORDS.len()) for keywords, then symbols
+const KEYWORD_BASE: Kind = 8;
+const SYMBOL_BASE: Kind = KEYWORD_BASE + KEYWORDS.len() as Kind;
+const UNKNOWN: Kind = SYMBOL_BASE + SYMBOLS.len() as Kind;
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '\''
+}
+
+fn is_symbol_char(c: char) -> bool {
+    "!#$%&*+./<=>?@\\^|-~:".contains(c)
+}
+
+fn is_special_char(c: char) -> bool {
+    "(),;[]`{}".contains(c)
+}
+
+fn keyword(spelling: &str) -> Option<Kind> {
+    KEYWORDS
+        .iter()
+        .position(|k| *k == spelling)
+        .map(|index| KEYWORD_BASE + index as Kind)
+}
+
+fn symbol(spelling: &str) -> Option<Kind> {
+    SYMBOLS
+        .iter()
+        .position(|s| *s == spelling)
+        .map(|index| SYMBOL_BASE + index as Kind)
+}
+
+/// Lexes the source into tokens, with whether each one starts a line
+fn lex(content: &str) -> Vec<(Token, bool)> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut res = vec![];
+    let mut i = 0;
+    let mut pos = Position::new();
+    let mut first_on_line = true;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // drop whitespace
+        if c.is_whitespace() {
+            if c == '\n' {
+                first_on_line = true;
+            }
+            pos.advance(&chars[i..i + 1]);
+            i += 1;
+            continue;
+        }
+
+        // drop line comments: two or more dashes not followed by a symbol, unlike `-->`
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            let mut dashes = i;
+            while dashes < chars.len() && chars[dashes] == '-' {
+                dashes += 1;
+            }
+            if !chars.get(dashes).is_some_and(|c| is_symbol_char(*c)) {
+                let mut end = dashes;
+                while end < chars.len() && chars[end] != '\n' {
+                    end += 1;
+                }
+                pos.advance(&chars[i..end]);
+                i = end;
+                continue;
+            }
+        }
+
+        // drop nested block comments and pragmas
+        if c == '{' && chars.get(i + 1) == Some(&'-') {
+            let mut depth = 0;
+            let mut end = i;
+            while end < chars.len() {
+                if chars[end] == '{' && chars.get(end + 1) == Some(&'-') {
+                    depth += 1;
+                    end += 2;
+                } else if chars[end] == '-' && chars.get(end + 1) == Some(&'}') {
+                    depth -= 1;
+                    end += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    end += 1;
+                }
+            }
+            let end = end.min(chars.len());
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        let end;
+        let kind;
+        if c.is_ascii_digit() {
+            // 42, 0xFF, 0o17, 0b1010, 1_000, 3.14, 1e-3
+            let mut j = i;
+            if c == '0' && matches!(chars.get(i + 1), Some('x' | 'X' | 'o' | 'O' | 'b' | 'B')) {
+                j += 2;
+                while j < chars.len() && (chars[j].is_ascii_hexdigit() || chars[j] == '_') {
+                    j += 1;
+                }
+            } else {
+                while j < chars.len() && (chars[j].is_ascii_digit() || chars[j] == '_') {
+                    j += 1;
+                }
+                if j + 1 < chars.len() && chars[j] == '.' && chars[j + 1].is_ascii_digit() {
+                    j += 1;
+                    while j < chars.len() && (chars[j].is_ascii_digit() || chars[j] == '_') {
+                        j += 1;
+                    }
+                }
+                if matches!(chars.get(j), Some('e' | 'E')) {
+                    let mut k = j + 1;
+                    if matches!(chars.get(k), Some('+' | '-')) {
+                        k += 1;
+                    }
+                    if chars.get(k).is_some_and(|c| c.is_ascii_digit()) {
+                        j = k;
+                        while j < chars.len() && chars[j].is_ascii_digit() {
+                            j += 1;
+                        }
+                    }
+                }
+            }
+            end = j;
+            kind = NUMBER;
+        } else if c.is_alphabetic() || c == '_' {
+            let mut j = i;
+            loop {
+                while j < chars.len() && is_identifier_char(chars[j]) {
+                    j += 1;
+                }
+                // qualified name: Data.Map.lookup
+                if chars[i].is_uppercase()
+                    && chars.get(j) == Some(&'.')
+                    && chars.get(j + 1).is_some_and(|c| c.is_alphabetic())
+                {
+                    j += 1;
+                    continue;
+                }
+                break;
+            }
+            let word: String = chars[i..j].iter().collect();
+            end = j;
+            kind = keyword(&word).unwrap_or(0);
+        } else if c == '\''
+            && (chars.get(i + 1) == Some(&'\\')
+                || (chars.get(i + 1).is_some_and(|c| *c != '\'')
+                    && chars.get(i + 2) == Some(&'\'')))
+        {
+            // character literal: 'a', '\n', '\''
+            let mut j = i + 1;
+            if chars[j] == '\\' {
+                j += 2;
+            }
+            while j < chars.len() && chars[j] != '\'' && chars[j] != '\n' {
+                j += 1;
+            }
+            end = (j + 1).min(chars.len());
+            kind = CHARACTER;
+        } else if c == '"' {
+            // string literal with backslash escapes
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '"' && chars[j] != '\n' {
+                if chars[j] == '\\' {
+                    j += 1;
+                }
+                j += 1;
+            }
+            end = (j + 1).min(chars.len());
+            kind = STRING;
+        } else if is_special_char(c) || c == '\'' {
+            end = i + 1;
+            kind = symbol(&c.to_string()).unwrap_or(UNKNOWN);
+        } else if is_symbol_char(c) {
+            let mut j = i;
+            while j < chars.len() && is_symbol_char(chars[j]) {
+                j += 1;
+            }
+            let word: String = chars[i..j].iter().collect();
+            end = j;
+            kind = symbol(&word).unwrap_or(OPERATOR);
+        } else {
+            end = i + 1;
+            kind = UNKNOWN;
+        }
+
+        let start = pos;
+        pos.advance(&chars[i..end]);
+        res.push((
+            start.token(&pos, kind, chars[i..end].iter().collect()),
+            first_on_line,
+        ));
+        first_on_line = false;
+        i = end;
+    }
+    res
+}
+
+enum Context {
+    // block opened by layout, with its indentation and whether it is opened by `let`
+    Implicit { column: u32, is_let: bool },
+    // braces, parentheses or brackets, which suspend layout,
+    // and whether the braces delimit a block
+    Explicit { block: bool },
+}
+
+// a brace or semicolon implied by layout, placed at the following token
+fn layout_token(kind: Kind, at: &Token) -> Token {
+    Token {
+        kind,
+        spelling: String::new(),
+        line: at.line,
+        column: at.column,
+        end_line: at.line,
+        end_column: at.column,
+        bytes: at.bytes.start..at.bytes.start,
+    }
+}
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    let tokens = lex(content);
+    let mut res = vec![];
+    let mut stack: Vec<Context> = vec![];
+    // a layout block is expected after `where`, `let`, `do` or `of`,
+    // or at the top of a module without a header
+    let mut pending: Option<bool> = match tokens.first() {
+        Some((token, _)) if token.spelling == "module" || token.spelling == "{" => None,
+        _ => Some(false),
+    };
+
+    for (mut token, first_on_line) in tokens {
+        let mut check_indentation = first_on_line;
+        if let Some(is_let) = pending.take() {
+            if token.spelling == "{" {
+                // explicit block, which is tokenized the same as layout
+                stack.push(Context::Explicit { block: true });
+                res.push(Token {
+                    kind: LAYOUT_OPEN,
+                    ..token
+                });
+                continue;
+            }
+            let enclosing = match stack.last() {
+                Some(Context::Implicit { column, .. }) => *column,
+                _ => 0,
+            };
+            res.push(layout_token(LAYOUT_OPEN, &token));
+            if token.column > enclosing {
+                stack.push(Context::Implicit {
+                    column: token.column,
+                    is_let,
+                });
+                check_indentation = false;
+            } else {
+                // empty block
+                res.push(layout_token(LAYOUT_CLOSE, &token));
+            }
+        }
+
+        if check_indentation {
+            while let Some(Context::Implicit { column, .. }) = stack.last() {
+                if token.column < *column {
+                    res.push(layout_token(LAYOUT_CLOSE, &token));
+                    stack.pop();
+                } else {
+                    if token.column == *column {
+                        res.push(layout_token(LAYOUT_SEPARATOR, &token));
+                    }
+                    break;
+                }
+            }
+        }
+
+        match token.spelling.as_str() {
+            // `in` ends the block of its `let`
+            "in" => {
+                if let Some(Context::Implicit { is_let: true, .. }) = stack.last() {
+                    res.push(layout_token(LAYOUT_CLOSE, &token));
+                    stack.pop();
+                }
+            }
+            ";" => {
+                if let Some(Context::Explicit { block: true }) = stack.last() {
+                    token.kind = LAYOUT_SEPARATOR;
+                }
+            }
+            "{" | "(" | "[" => stack.push(Context::Explicit { block: false }),
+            // closing brackets end the layout blocks inside them
+            "}" | ")" | "]" => {
+                while let Some(Context::Implicit { .. }) = stack.last() {
+                    res.push(layout_token(LAYOUT_CLOSE, &token));
+                    stack.pop();
+                }
+                if let Some(Context::Explicit { block: true }) = stack.pop() {
+                    token.kind = LAYOUT_CLOSE;
+                }
+            }
+            "where" | "let" | "do" | "of" => {
+                pending = Some(token.spelling == "let");
+            }
+            _ => {}
+        }
+        res.push(token);
+    }
+
+    // close the remaining blocks at the end of the last token
+    if let Some(last) = res.last().cloned() {
+        if pending.is_some() {
+            res.push(layout_token(LAYOUT_OPEN, &last));
+            res.push(layout_token(LAYOUT_CLOSE, &last));
+        }
+        for context in stack.iter().rev() {
+            if let Context::Implicit { .. } = context {
+                res.push(layout_token(LAYOUT_CLOSE, &last));
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize_str, LAYOUT_CLOSE, LAYOUT_OPEN, LAYOUT_SEPARATOR};
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "module Main where\n\n-- entry\nmain :: IO ()\nmain = do\n  putStrLn \"Hello\"\n  print (1 + 2)\n";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "module");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[1].spelling, "Main");
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(tokens[1].column, 8);
+
+        // block opened by `where`
+        assert_eq!(tokens[3].kind, LAYOUT_OPEN);
+        assert_eq!(tokens[4].spelling, "main");
+        assert_eq!(tokens[4].line, 4);
+        assert_eq!(tokens[4].column, 1);
+
+        // next declaration
+        assert_eq!(tokens[9].kind, LAYOUT_SEPARATOR);
+        assert_eq!(tokens[10].spelling, "main");
+        assert_eq!(tokens[10].line, 5);
+
+        // block opened by `do`
+        assert_eq!(tokens[13].kind, LAYOUT_OPEN);
+        assert_eq!(tokens[14].spelling, "putStrLn");
+        assert_eq!(tokens[14].line, 6);
+        assert_eq!(tokens[14].column, 3);
+
+        assert_eq!(tokens[15].spelling, "\"Hello\"");
+        assert_eq!(tokens[16].kind, LAYOUT_SEPARATOR);
+
+        // both blocks are closed at the end
+        let n = tokens.len();
+        assert_eq!(tokens[n - 2].kind, LAYOUT_CLOSE);
+        assert_eq!(tokens[n - 1].kind, LAYOUT_CLOSE);
+    }
+
+    #[test]
+    fn test_layout() {
+        // explicit braces and layout give the same kinds
+        let layout =
+            tokenize_str("f x = y + z\n  where\n    y = x\n    z = let a = 1 in a\n").unwrap();
+        let explicit = tokenize_str("f x = y + z where { y = x; z = let { a = 1 } in a }").unwrap();
+        let layout_kind: Vec<Kind> = layout.iter().map(|t| t.kind).collect();
+        let explicit_kind: Vec<Kind> = explicit.iter().map(|t| t.kind).collect();
+        assert_eq!(layout_kind, explicit_kind);
+
+        // moving a binding out of the `where` block is visible
+        let moved = tokenize_str("f x = y + z\n  where\n    y = x\nz = let a = 1 in a\n").unwrap();
+        let moved_kind: Vec<Kind> = moved.iter().map(|t| t.kind).collect();
+        assert_ne!(layout_kind, moved_kind);
+    }
+
+    #[test]
+    fn test_rename() {
+        let left = tokenize_str("go n acc\n  | n == 0 = acc\n  | otherwise = go (n - 1) (acc * n)")
+            .unwrap();
+        let right =
+            tokenize_str("loop k r\n  | k == 0 = r\n  | otherwise = loop (k - 1) (r * k)").unwrap();
+        let left_kind: Vec<Kind> = left.iter().map(|t| t.kind).collect();
+        let right_kind: Vec<Kind> = right.iter().map(|t| t.kind).collect();
+        assert_eq!(left_kind, right_kind);
+    }
+}
@@ -1,32 +1,51 @@
+use super::fallback::{recover, Syntax};
+use super::scanner::LineIndex;
 use crate::lang::Tokenize;
-use crate::token::Token;
+use crate::token::{Diagnostic, Kind, Token, Tokenized};
+use boa_ast::{Keyword, Punctuator};
 use boa_interner::Interner;
 use boa_parser::lexer::token::TokenKind::*;
+use boa_parser::lexer::Error;
 use boa_parser::Lexer;
 use std::io::Cursor;
 
 pub struct JavaScript;
 
 impl Tokenize for JavaScript {
-    fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
-        tokenize_str(content)
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, tokenize_str)
     }
 }
 
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"', '\'', '`'],
+};
+
 fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let mut res = vec![];
+    let lines = LineIndex::new(content);
     let mut lexer = Lexer::new(Cursor::new(content));
     let mut interner = Interner::new();
-    while let Some(token) = lexer.next(&mut interner)? {
+    while let Some(token) = lexer.next(&mut interner).map_err(|err| match err {
+        Error::Syntax(message, position) => Diagnostic {
+            line: position.line_number(),
+            column: position.column_number(),
+            message: message.to_string(),
+        }
+        .into(),
+        err => anyhow::Error::from(err),
+    })? {
         let kind = match token.kind() {
             BooleanLiteral(_) => 0,
             EOF => continue,
             IdentifierName(_) => 1,
             PrivateIdentifier(_) => 2,
-            Keyword(_) => 3,
+            Keyword((keyword, _)) => keyword_kind(*keyword),
             NullLiteral(_) => 4,
             NumericLiteral(_) => 5,
-            Punctuator(_) => 6,
+            Punctuator(punctuator) => punctuator_kind(*punctuator),
             StringLiteral(_) => 7,
             TemplateNoSubstitution(_) => 8,
             TemplateMiddle(_) => 9,
@@ -34,16 +53,124 @@ fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
             LineTerminator => continue,
             Comment => continue,
         };
-        res.push(Token {
-            kind,
-            spelling: token.kind().to_string(&interner),
-            line: token.span().start().line_number(),
-            column: token.span().start().column_number(),
-        });
+        let (start, end) = (token.span().start(), token.span().end());
+        let bytes = lines.byte(start.line_number(), start.column_number())
+            ..lines.byte(end.line_number(), end.column_number());
+        res.push(lines.token(kind, token.kind().to_string(&interner), bytes));
     }
     Ok(res)
 }
 
+/// Keyword: [11, 51]
+fn keyword_kind(keyword: Keyword) -> Kind {
+    match keyword {
+        Keyword::Await => 11,
+        Keyword::Async => 12,
+        Keyword::Break => 13,
+        Keyword::Case => 14,
+        Keyword::Catch => 15,
+        Keyword::Class => 16,
+        Keyword::Continue => 17,
+        Keyword::Const => 18,
+        Keyword::Debugger => 19,
+        Keyword::Default => 20,
+        Keyword::Delete => 21,
+        Keyword::Do => 22,
+        Keyword::Else => 23,
+        Keyword::Enum => 24,
+        Keyword::Extends => 25,
+        Keyword::Export => 26,
+        Keyword::False => 27,
+        Keyword::Finally => 28,
+        Keyword::For => 29,
+        Keyword::Function => 30,
+        Keyword::If => 31,
+        Keyword::In => 32,
+        Keyword::InstanceOf => 33,
+        Keyword::Import => 34,
+        Keyword::Let => 35,
+        Keyword::New => 36,
+        Keyword::Null => 37,
+        Keyword::Of => 38,
+        Keyword::Return => 39,
+        Keyword::Super => 40,
+        Keyword::Switch => 41,
+        Keyword::This => 42,
+        Keyword::Throw => 43,
+        Keyword::True => 44,
+        Keyword::Try => 45,
+        Keyword::TypeOf => 46,
+        Keyword::Var => 47,
+        Keyword::Void => 48,
+        Keyword::While => 49,
+        Keyword::With => 50,
+        Keyword::Yield => 51,
+    }
+}
+
+/// Punctuator: [52, 108]
+fn punctuator_kind(punctuator: Punctuator) -> Kind {
+    match punctuator {
+        Punctuator::Add => 52,
+        Punctuator::And => 53,
+        Punctuator::Arrow => 54,
+        Punctuator::Assign => 55,
+        Punctuator::AssignAdd => 56,
+        Punctuator::AssignAnd => 57,
+        Punctuator::AssignBoolAnd => 58,
+        Punctuator::AssignBoolOr => 59,
+        Punctuator::AssignCoalesce => 60,
+        Punctuator::AssignDiv => 61,
+        Punctuator::AssignLeftSh => 62,
+        Punctuator::AssignMod => 63,
+        Punctuator::AssignMul => 64,
+        Punctuator::AssignOr => 65,
+        Punctuator::AssignPow => 66,
+        Punctuator::AssignRightSh => 67,
+        Punctuator::AssignSub => 68,
+        Punctuator::AssignURightSh => 69,
+        Punctuator::AssignXor => 70,
+        Punctuator::BoolAnd => 71,
+        Punctuator::BoolOr => 72,
+        Punctuator::Coalesce => 73,
+        Punctuator::CloseBlock => 74,
+        Punctuator::CloseBracket => 75,
+        Punctuator::CloseParen => 76,
+        Punctuator::Colon => 77,
+        Punctuator::Comma => 78,
+        Punctuator::Dec => 79,
+        Punctuator::Div => 80,
+        Punctuator::Dot => 81,
+        Punctuator::Eq => 82,
+        Punctuator::GreaterThan => 83,
+        Punctuator::GreaterThanOrEq => 84,
+        Punctuator::Inc => 85,
+        Punctuator::LeftSh => 86,
+        Punctuator::LessThan => 87,
+        Punctuator::LessThanOrEq => 88,
+        Punctuator::Mod => 89,
+        Punctuator::Mul => 90,
+        Punctuator::Neg => 91,
+        Punctuator::Not => 92,
+        Punctuator::NotEq => 93,
+        Punctuator::OpenBlock => 94,
+        Punctuator::OpenBracket => 95,
+        Punctuator::OpenParen => 96,
+        Punctuator::Optional => 97,
+        Punctuator::Or => 98,
+        Punctuator::Exp => 99,
+        Punctuator::Question => 100,
+        Punctuator::RightSh => 101,
+        Punctuator::Semicolon => 102,
+        Punctuator::Spread => 103,
+        Punctuator::StrictEq => 104,
+        Punctuator::StrictNotEq => 105,
+        Punctuator::Sub => 106,
+        Punctuator::URightSh => 107,
+        Punctuator::Xor => 108,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::tokenize_str;
@@ -76,4 +203,25 @@ mod tests {
         assert_eq!(tokens[8].line, 2);
         assert_eq!(tokens[8].column, 1);
     }
+
+    #[test]
+    fn test_kinds() {
+        let code = "while (a) b * c; if (a) b + c;";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "while");
+        assert_eq!(tokens[8].spelling, "if");
+        assert_ne!(tokens[0].kind, tokens[8].kind);
+
+        assert_eq!(tokens[5].spelling, "*");
+        assert_eq!(tokens[13].spelling, "+");
+        assert_ne!(tokens[5].kind, tokens[13].kind);
+
+        // the same parentheses, identifiers and semicolons
+        for i in [1, 2, 3, 4, 6, 7] {
+            assert_eq!(tokens[i].kind, tokens[i + 8].kind);
+        }
+    }
 }
@@ -1,19 +1,33 @@
+use super::fallback::{recover, Syntax};
+use super::scanner::LineIndex;
 use crate::lang::Tokenize;
-use crate::token::Token;
-use full_moon::tokenizer::tokens;
+use crate::token::{Diagnostic, Kind, Token, Tokenized};
 use full_moon::tokenizer::TokenKind::*;
+use full_moon::tokenizer::{tokens, Symbol, TokenType};
 
 pub struct Lua;
 
 impl Tokenize for Lua {
-    fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
-        tokenize_str(content)
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, tokenize_str)
     }
 }
 
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("--[[", "]]")],
+    quotes: &['"', '\''],
+};
+
 fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let mut res = vec![];
-    for token in tokens(content)? {
+    let lines = LineIndex::new(content);
+    let tokens = tokens(content).map_err(|err| Diagnostic {
+        line: err.position().line() as u32,
+        column: err.position().character() as u32,
+        message: err.error().to_string(),
+    })?;
+    for token in tokens {
         let kind = match token.token_kind() {
             Eof => continue,
             Identifier => 0,
@@ -22,20 +36,82 @@ fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
             Shebang => 2,
             SingleLineComment => continue,
             StringLiteral => 3,
-            Symbol => 4,
+            Symbol => match token.token_type() {
+                TokenType::Symbol { symbol } => symbol_kind(*symbol),
+                _ => 4,
+            },
             Whitespace => continue,
-            _ => todo!(),
+            // kinds of dialects, e.g. interpolated strings in Luau
+            _ => 5,
         };
-        res.push(Token {
-            kind,
-            spelling: token.to_string(),
-            line: token.start_position().line() as u32,
-            column: token.start_position().character() as u32,
-        });
+        let bytes = token.start_position().bytes()..token.end_position().bytes();
+        res.push(lines.token(kind, token.to_string(), bytes));
     }
     Ok(res)
 }
 
+/// Keyword: [6, 26], operator: [27, 59]
+fn symbol_kind(symbol: Symbol) -> Kind {
+    match symbol {
+        Symbol::And => 6,
+        Symbol::Break => 7,
+        Symbol::Do => 8,
+        Symbol::Else => 9,
+        Symbol::ElseIf => 10,
+        Symbol::End => 11,
+        Symbol::False => 12,
+        Symbol::For => 13,
+        Symbol::Function => 14,
+        Symbol::If => 15,
+        Symbol::In => 16,
+        Symbol::Local => 17,
+        Symbol::Nil => 18,
+        Symbol::Not => 19,
+        Symbol::Or => 20,
+        Symbol::Repeat => 21,
+        Symbol::Return => 22,
+        Symbol::Then => 23,
+        Symbol::True => 24,
+        Symbol::Until => 25,
+        Symbol::While => 26,
+        Symbol::PlusEqual => 27,
+        Symbol::MinusEqual => 28,
+        Symbol::StarEqual => 29,
+        Symbol::SlashEqual => 30,
+        Symbol::PercentEqual => 31,
+        Symbol::CaretEqual => 32,
+        Symbol::TwoDotsEqual => 33,
+        Symbol::Caret => 34,
+        Symbol::Colon => 35,
+        Symbol::Comma => 36,
+        Symbol::Ellipse => 37,
+        Symbol::TwoDots => 38,
+        Symbol::Dot => 39,
+        Symbol::TwoEqual => 40,
+        Symbol::Equal => 41,
+        Symbol::GreaterThanEqual => 42,
+        Symbol::GreaterThan => 43,
+        Symbol::Hash => 44,
+        Symbol::LeftBracket => 45,
+        Symbol::LeftBrace => 46,
+        Symbol::LeftParen => 47,
+        Symbol::LessThanEqual => 48,
+        Symbol::LessThan => 49,
+        Symbol::Minus => 50,
+        Symbol::Percent => 51,
+        Symbol::Plus => 52,
+        Symbol::RightBrace => 53,
+        Symbol::RightBracket => 54,
+        Symbol::RightParen => 55,
+        Symbol::Semicolon => 56,
+        Symbol::Slash => 57,
+        Symbol::Star => 58,
+        Symbol::TildeEqual => 59,
+        // symbols of dialects, e.g. `//` in Lua 5.3
+        _ => 4,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::tokenize_str;
@@ -69,4 +145,46 @@ mod tests {
         assert_eq!(tokens[5].line, 2);
         assert_eq!(tokens[5].column, 1);
     }
+
+    #[test]
+    fn test_kinds() {
+        let code = "while a do b = c * d end
+if a then b = c + d end";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "while");
+        assert_eq!(tokens[9].spelling, "if");
+        assert_ne!(tokens[0].kind, tokens[9].kind);
+        assert_ne!(tokens[2].kind, tokens[11].kind);
+
+        assert_eq!(tokens[6].spelling, "*");
+        assert_eq!(tokens[15].spelling, "+");
+        assert_ne!(tokens[6].kind, tokens[15].kind);
+
+        // `end`, `=` and identifiers are the same
+        for i in [1, 3, 4, 5, 7, 8] {
+            assert_eq!(tokens[i].kind, tokens[i + 9].kind);
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        use crate::lang::Tokenize;
+
+        let code = "local s = \"abc\nprint(s)";
+        let tokenized = super::Lua.tokenize_str(code);
+
+        eprintln!("{:?}", tokenized);
+
+        assert_eq!(tokenized.diagnostics.len(), 1);
+        assert_eq!(tokenized.diagnostics[0].line, 1);
+
+        // the next line is tokenized as usual
+        assert_eq!(tokenized.tokens[4].spelling, "print");
+        assert_eq!(tokenized.tokens[4].kind, 0);
+        assert_eq!(tokenized.tokens[4].line, 2);
+        assert_eq!(tokenized.tokens[4].column, 1);
+    }
 }
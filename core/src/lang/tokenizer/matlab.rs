@@ -0,0 +1,371 @@
+use super::{
+    fallback::{recover, Syntax},
+    scanner::Position,
+};
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+
+pub struct Matlab;
+
+impl Tokenize for Matlab {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, tokenize_str)
+    }
+}
+
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["%"],
+    block_comments: &[("%{", "%}")],
+    quotes: &['"'],
+};
+
+// MATLAB keywords, plus Octave-only ones
+const KEYWORDS: [&str; 24] = [
+    "break",
+    "case",
+    "catch",
+    "classdef",
+    "continue",
+    "else",
+    "elseif",
+    "end",
+    "for",
+    "function",
+    "global",
+    "if",
+    "otherwise",
+    "parfor",
+    "persistent",
+    "return",
+    "spmd",
+    "switch",
+    "try",
+    "while",
+    "do",
+    "until",
+    "unwind_protect",
+    "unwind_protect_cleanup",
+];
+
+// Octave block terminators, tokenized the same as `end`
+const OCTAVE_ENDS: [&str; 12] = [
+    "endfunction",
+    "endif",
+    "endfor",
+    "endparfor",
+    "endwhile",
+    "endswitch",
+    "end_try_catch",
+    "end_unwind_protect",
+    "endclassdef",
+    "endmethods",
+    "endproperties",
+    "endspmd",
+];
+
+// longer operators first, so that the longest one matches
+const OPERATORS: [&str; 42] = [
+    "==", "~=", "!=", "<=", ">=", "&&", "||", ".*", "./", ".\\", ".^", ".'", "++", "--", "+=",
+    "-=", "*=", "/=", "+", "-", "*", "/", "\\", "^", "'", "<", ">", "&", "|", "~", "!", "=", ":",
+    ",", ";", "(", ")", "[", "]", "{", "}", ".",
+];
+
+// kind: 0 for identifiers, [1, 3] for literals
+const NUMBER: Kind = 1;
+const CHAR_ARRAY: Kind = 2;
+const STRING: Kind = 3;
+// kind: `end` as the last index, e.g. `a(end)`
+const END_INDEX: Kind = 4;
+const AT: Kind = 5;
+// kind: [6, 6+KEYWORDS.len()) for keywords, then operators
+const KEYWORD_BASE: Kind = 6;
+const OPERATOR_BASE: Kind = KEYWORD_BASE + KEYWORDS.len() as Kind;
+const UNKNOWN: Kind = OPERATOR_BASE + OPERATORS.len() as Kind;
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn keyword(word: &str) -> Option<Kind> {
+    KEYWORDS
+        .iter()
+        .position(|k| *k == word)
+        .map(|index| KEYWORD_BASE + index as Kind)
+}
+
+fn operator(op: &str) -> Kind {
+    OPERATOR_BASE + OPERATORS.iter().position(|o| *o == op).unwrap() as Kind
+}
+
+// whether `text` at `i` is alone on its line, used for block comments
+fn is_alone_on_line(chars: &[char], line_start: usize, i: usize, text: &str) -> bool {
+    let before_blank = chars[line_start..i].iter().all(|c| *c == ' ' || *c == '\t');
+    let len = text.chars().count();
+    let matches = text
+        .chars()
+        .enumerate()
+        .all(|(k, ch)| chars.get(i + k) == Some(&ch));
+    let mut end = i + len;
+    while end < chars.len() && chars[end] != '\n' {
+        if !chars[end].is_whitespace() {
+            return false;
+        }
+        end += 1;
+    }
+    before_blank && matches
+}
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut res: Vec<Token> = vec![];
+    let mut i = 0;
+    let mut pos = Position::new();
+    let mut line_start = 0;
+    // open brackets, to tell `end` of a block from `end` as an index
+    let mut brackets: Vec<char> = vec![];
+    // whether whitespace precedes the current character
+    let mut after_space = false;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // drop whitespace
+        if c.is_whitespace() {
+            if c == '\n' {
+                line_start = i + 1;
+            }
+            after_space = true;
+            pos.advance(&chars[i..i + 1]);
+            i += 1;
+            continue;
+        }
+
+        // drop block comments: `%{` and `%}` alone on their lines, may be nested
+        if (c == '%' || c == '#') && is_alone_on_line(&chars, line_start, i, &format!("{}{{", c)) {
+            let mut depth = 0;
+            let mut end = i;
+            let mut start = line_start;
+            while end < chars.len() {
+                if is_alone_on_line(&chars, start, end, "%{")
+                    || is_alone_on_line(&chars, start, end, "#{")
+                {
+                    depth += 1;
+                } else if is_alone_on_line(&chars, start, end, "%}")
+                    || is_alone_on_line(&chars, start, end, "#}")
+                {
+                    depth -= 1;
+                    if depth == 0 {
+                        end += 2;
+                        break;
+                    }
+                }
+                if chars[end] == '\n' {
+                    start = end + 1;
+                }
+                end += 1;
+            }
+            let end = end.min(chars.len());
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        // drop line comments, and continuations whose rest is a comment
+        if c == '%'
+            || c == '#'
+            || (c == '.' && chars.get(i + 1) == Some(&'.') && chars.get(i + 2) == Some(&'.'))
+        {
+            let mut end = i;
+            while end < chars.len() && chars[end] != '\n' {
+                end += 1;
+            }
+            // a continuation joins the next line
+            if c == '.' && end < chars.len() {
+                end += 1;
+                line_start = end;
+            }
+            after_space = true;
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        let end;
+        let kind;
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            // 42, 3.14, .5, 1e-3, 2i, 0x1F
+            let mut j = i;
+            while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '.') {
+                // do not take element-wise operators: 1.*x
+                if chars[j] == '.'
+                    && chars
+                        .get(j + 1)
+                        .is_some_and(|c| matches!(c, '*' | '/' | '\\' | '^' | '\''))
+                {
+                    break;
+                }
+                j += 1;
+            }
+            if j < chars.len()
+                && (chars[j] == '+' || chars[j] == '-')
+                && (chars[j - 1] == 'e' || chars[j - 1] == 'E')
+            {
+                j += 1;
+                while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '.') {
+                    j += 1;
+                }
+            }
+            end = j;
+            kind = NUMBER;
+        } else if c.is_alphabetic() {
+            let mut j = i;
+            while j < chars.len() && is_identifier_char(chars[j]) {
+                j += 1;
+            }
+            let word: String = chars[i..j].iter().collect();
+            kind = if word == "end" && matches!(brackets.last(), Some('(' | '{')) {
+                // last index
+                END_INDEX
+            } else if OCTAVE_ENDS.contains(&word.as_str()) {
+                keyword("end").unwrap()
+            } else {
+                keyword(&word).unwrap_or(0)
+            };
+            end = j;
+        } else if c == '\''
+            && res.last().is_some_and(|t| {
+                // transpose after a value, unless separated by a space inside
+                // brackets, where it starts an element: [a 'b']
+                let is_value = t.kind == 0
+                    || t.kind == NUMBER
+                    || t.kind == END_INDEX
+                    || t.kind == operator(")")
+                    || t.kind == operator("]")
+                    || t.kind == operator("}")
+                    || t.kind == operator("'")
+                    || t.kind == operator(".'");
+                is_value && !(after_space && matches!(brackets.last(), Some('[' | '{')))
+            })
+        {
+            end = i + 1;
+            kind = operator("'");
+        } else if c == '\'' || c == '"' {
+            // single-quoted char array or double-quoted string,
+            // with doubled quotes as escapes
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '\n' {
+                if chars[j] == c {
+                    if chars.get(j + 1) == Some(&c) {
+                        j += 2;
+                        continue;
+                    }
+                    break;
+                }
+                // Octave escapes in double-quoted strings
+                if c == '"' && chars[j] == '\\' {
+                    j += 1;
+                }
+                j += 1;
+            }
+            end = (j + 1).min(chars.len());
+            kind = if c == '\'' { CHAR_ARRAY } else { STRING };
+        } else if c == '@' {
+            // function handle or anonymous function
+            end = i + 1;
+            kind = AT;
+        } else if let Some(index) = OPERATORS.iter().position(|op| {
+            op.chars()
+                .enumerate()
+                .all(|(k, ch)| chars.get(i + k) == Some(&ch))
+        }) {
+            end = i + OPERATORS[index].chars().count();
+            kind = OPERATOR_BASE + index as Kind;
+            match c {
+                '(' | '[' | '{' => brackets.push(c),
+                ')' | ']' | '}' => {
+                    brackets.pop();
+                }
+                _ => {}
+            }
+        } else {
+            end = i + 1;
+            kind = UNKNOWN;
+        }
+
+        let start = pos;
+        pos.advance(&chars[i..end]);
+        res.push(start.token(&pos, kind, chars[i..end].iter().collect()));
+        after_space = false;
+        i = end;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keyword, tokenize_str, CHAR_ARRAY, END_INDEX};
+
+    #[test]
+    fn test_tokenize() {
+        let code =
+            "function y = f(x)\n  % comment\n  y = x' * x; %{ not a block %}\n  s = 'it''s';\nend";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "function");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[3].spelling, "f");
+        assert_eq!(tokens[3].line, 1);
+        assert_eq!(tokens[3].column, 14);
+
+        // comment is dropped
+        assert_eq!(tokens[7].spelling, "y");
+        assert_eq!(tokens[7].line, 3);
+        assert_eq!(tokens[7].column, 3);
+
+        // transpose
+        assert_eq!(tokens[10].spelling, "'");
+        assert_eq!(tokens[10].column, 8);
+
+        // char array with escaped quote
+        assert_eq!(tokens[16].spelling, "'it''s'");
+        assert_eq!(tokens[16].kind, CHAR_ARRAY);
+        assert_eq!(tokens[16].line, 4);
+        assert_eq!(tokens[16].column, 7);
+
+        assert_eq!(tokens[18].spelling, "end");
+        assert_eq!(tokens[18].line, 5);
+        assert_eq!(tokens[18].column, 1);
+    }
+
+    #[test]
+    fn test_end_and_comments() {
+        let code =
+            "%{\nblock comment\n%}\nfor i = 1:n\n  a(end) = b{end}';\nendfor\nx = [a' 'str'];";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "for");
+        assert_eq!(tokens[0].line, 4);
+
+        // `end` as index
+        assert_eq!(tokens[8].spelling, "end");
+        assert_eq!(tokens[8].kind, END_INDEX);
+        assert_eq!(tokens[13].spelling, "end");
+        assert_eq!(tokens[13].kind, END_INDEX);
+
+        // `endfor` is the same as `end`
+        assert_eq!(tokens[17].spelling, "endfor");
+        assert_eq!(tokens[17].kind, keyword("end").unwrap());
+
+        // transpose and string inside brackets
+        assert_eq!(tokens[22].spelling, "'");
+        assert_eq!(tokens[23].spelling, "'str'");
+        assert_eq!(tokens[23].kind, CHAR_ARRAY);
+    }
+}
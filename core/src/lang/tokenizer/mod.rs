@@ -1,7 +1,22 @@
+pub mod assembly;
 pub mod cpp;
+pub mod external;
+pub mod fallback;
+pub mod go;
+pub mod grammar;
+pub mod haskell;
+pub mod java;
 pub mod javascript;
 pub mod lua;
+pub mod matlab;
+pub mod ocaml;
+pub mod prose;
 pub mod python;
 pub mod rust;
+pub mod scala;
+pub(crate) mod scanner;
 pub mod sql;
+pub mod systemverilog;
+pub mod typescript;
 pub mod verilog;
+pub mod vhdl;
@@ -0,0 +1,128 @@
+use super::{fallback::Syntax, grammar::tokenized, grammar::Grammar};
+use crate::lang::Tokenize;
+use crate::token::{Token, Tokenized};
+use std::path::Path;
+use tree_sitter::Tree;
+
+pub struct OCaml;
+
+impl Tokenize for OCaml {
+    fn tokenize_content(&self, path: &Path, content: &str) -> Tokenized {
+        let extension = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let tree = match extension.as_str() {
+            "mli" => GRAMMAR.parse_as(content, &tree_sitter_ocaml::language_ocaml_interface()),
+            _ => GRAMMAR.parse(content),
+        };
+        tokenized(content, &GRAMMAR.syntax, tree, |tree| {
+            GRAMMAR.tokenize_tree(content, tree)
+        })
+    }
+
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        tokenized(content, &GRAMMAR.syntax, parse_any(content), |tree| {
+            GRAMMAR.tokenize_tree(content, tree)
+        })
+    }
+}
+
+/// Implementations, whose symbol table also gives the kinds of interfaces
+pub static GRAMMAR: Grammar = Grammar::new(
+    "OCaml",
+    tree_sitter_ocaml::language_ocaml,
+    &["comment", "line_number_directive", "shebang"],
+    &[
+        "value_name",
+        "value_pattern",
+        "type_constructor",
+        "type_variable",
+        "module_name",
+        "module_type_name",
+        "constructor_name",
+        "tag",
+        "field_name",
+        "label_name",
+        "class_name",
+        "class_type_name",
+        "method_name",
+        "instance_variable_name",
+        "attribute_id",
+    ],
+    &[
+        &["number", "signed_number"],
+        &["string", "quoted_string", "character"],
+    ],
+    Syntax {
+        line_comments: &[],
+        block_comments: &[("(*", "*)")],
+        quotes: &['"'],
+    },
+);
+
+// an implementation, or an interface if only that parses
+fn parse_any(content: &str) -> anyhow::Result<Tree> {
+    let tree = GRAMMAR.parse(content)?;
+    if tree.root_node().has_error() {
+        let interface =
+            GRAMMAR.parse_as(content, &tree_sitter_ocaml::language_ocaml_interface())?;
+        if !interface.root_node().has_error() {
+            return Ok(interface);
+        }
+    }
+    Ok(tree)
+}
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    Ok(GRAMMAR.tokenize_tree(content, &parse_any(content)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize_str;
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "(* entry *)\nlet rec fact n =\n  if n = 0 then 1 else n * fact (n - 1)\n\nlet () = print_endline \"Hello, \\\"world\\\"\"\n";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        // comment is skipped
+        assert_eq!(tokens[0].spelling, "let");
+        assert_eq!(tokens[0].line, 2);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[2].spelling, "fact");
+        assert_eq!(tokens[2].line, 2);
+        assert_eq!(tokens[2].column, 9);
+
+        assert_eq!(tokens[5].spelling, "if");
+        assert_eq!(tokens[5].line, 3);
+        assert_eq!(tokens[5].column, 3);
+
+        // string literal is a single token
+        assert_eq!(tokens[25].spelling, "\"Hello, \\\"world\\\"\"");
+        assert_eq!(tokens[25].line, 5);
+        assert_eq!(tokens[25].column, 24);
+    }
+
+    #[test]
+    fn test_rename() {
+        let left = tokenize_str("let f a = match a with [] -> 0 | x :: _ -> x + 1").unwrap();
+        let right = tokenize_str("let g l = match l with [] -> 2 | hd :: _ -> hd + 3").unwrap();
+        let left_kind: Vec<Kind> = left.iter().map(|t| t.kind).collect();
+        let right_kind: Vec<Kind> = right.iter().map(|t| t.kind).collect();
+        assert_eq!(left_kind, right_kind);
+    }
+
+    #[test]
+    fn test_interface() {
+        let tokens = tokenize_str("type t\nval create : int -> t\n").unwrap();
+        assert_eq!(tokens[2].spelling, "val");
+        assert_eq!(tokens[2].line, 2);
+    }
+}
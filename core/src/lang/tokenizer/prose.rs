@@ -0,0 +1,485 @@
+use super::{
+    fallback::{self, recover, Syntax},
+    scanner::Position,
+};
+use crate::common::stable_hash;
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+use rust_stemmers::{Algorithm, Stemmer};
+use std::path::Path;
+
+/// Markup stripped before words are taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Plain,
+    Markdown,
+    Latex,
+}
+
+impl Format {
+    pub fn from_extension(extension: &str) -> Self {
+        match extension.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Format::Markdown,
+            "tex" | "ltx" => Format::Latex,
+            _ => Format::Plain,
+        }
+    }
+
+    /// Guess from content, treating anything that does not look like LaTeX
+    /// as Markdown, which leaves plain text mostly untouched
+    pub fn detect(content: &str) -> Self {
+        let is_latex = ["\\documentclass", "\\begin{", "\\section", "\\usepackage"]
+            .iter()
+            .any(|s| content.contains(s));
+        if is_latex {
+            Format::Latex
+        } else {
+            Format::Markdown
+        }
+    }
+}
+
+/// Word tokens of natural language text, case-folded and optionally stemmed
+#[derive(Default)]
+pub struct Prose {
+    pub stem: bool,
+}
+
+impl Tokenize for Prose {
+    fn tokenize_content(&self, path: &Path, content: &str) -> Tokenized {
+        let format = Format::from_extension(
+            path.extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default(),
+        );
+        recover(content, &SYNTAX, |content| {
+            tokenize_str(content, format, self.stem)
+        })
+    }
+
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        let format = Format::detect(content);
+        recover(content, &SYNTAX, |content| {
+            tokenize_str(content, format, self.stem)
+        })
+    }
+}
+
+const SYNTAX: Syntax = Syntax {
+    line_comments: &[],
+    block_comments: &[("<!--", "-->")],
+    quotes: &[],
+};
+
+// environments whose content is not prose
+const MATH_ENVIRONMENTS: [&str; 16] = [
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "alignat",
+    "alignat*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "math",
+    "displaymath",
+    "flalign",
+    "flalign*",
+];
+const CODE_ENVIRONMENTS: [&str; 5] = ["verbatim", "verbatim*", "lstlisting", "minted", "comment"];
+
+// commands whose arguments are keys, paths or lengths, with the number of
+// arguments to drop
+const COMMANDS: [(&str, usize); 27] = [
+    ("begin", 1),
+    ("end", 1),
+    ("cite", 1),
+    ("citep", 1),
+    ("citet", 1),
+    ("nocite", 1),
+    ("ref", 1),
+    ("eqref", 1),
+    ("pageref", 1),
+    ("autoref", 1),
+    ("cref", 1),
+    ("Cref", 1),
+    ("label", 1),
+    ("url", 1),
+    ("href", 1),
+    ("input", 1),
+    ("include", 1),
+    ("includegraphics", 1),
+    ("documentclass", 1),
+    ("usepackage", 1),
+    ("bibliography", 1),
+    ("bibliographystyle", 1),
+    ("vspace", 1),
+    ("hspace", 1),
+    ("setlength", 2),
+    ("newcommand", 2),
+    ("renewcommand", 2),
+];
+
+fn starts_with(chars: &[char], i: usize, text: &str) -> bool {
+    text.chars()
+        .enumerate()
+        .all(|(k, ch)| chars.get(i + k) == Some(&ch))
+}
+
+fn find(chars: &[char], from: usize, text: &str) -> Option<usize> {
+    (from..chars.len()).find(|i| starts_with(chars, *i, text))
+}
+
+fn line_end(chars: &[char], i: usize) -> usize {
+    find(chars, i, "\n").unwrap_or(chars.len())
+}
+
+// end of a balanced group opened at `i`, exclusive
+fn group_end(chars: &[char], i: usize, open: char, close: char) -> usize {
+    let mut depth = 0;
+    let mut j = i;
+    while j < chars.len() {
+        if chars[j] == '\\' {
+            j += 2;
+            continue;
+        }
+        if chars[j] == open {
+            depth += 1;
+        } else if chars[j] == close {
+            depth -= 1;
+            if depth == 0 {
+                return j + 1;
+            }
+        }
+        j += 1;
+    }
+    chars.len()
+}
+
+// end of inline math opened by `$` or `$$` at `i`, which must close within
+// the paragraph, so that prices such as $5 are kept
+fn dollar_end(chars: &[char], i: usize) -> Option<usize> {
+    let delimiter = if chars.get(i + 1) == Some(&'$') {
+        "$$"
+    } else {
+        "$"
+    };
+    let start = i + delimiter.len();
+    if delimiter == "$" && chars.get(start).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    let mut j = start;
+    while j < chars.len() {
+        if chars[j] == '\\' {
+            j += 2;
+            continue;
+        }
+        if starts_with(chars, j, "\n\n") {
+            return None;
+        }
+        if starts_with(chars, j, delimiter) {
+            return Some(j + delimiter.len());
+        }
+        j += 1;
+    }
+    None
+}
+
+// blank out markup shared by all formats: HTML comments and URLs
+fn mask_common(chars: &[char], keep: &mut [bool]) {
+    let mut i = 0;
+    while i < chars.len() {
+        let end = if starts_with(chars, i, "<!--") {
+            find(chars, i, "-->").map_or(chars.len(), |j| j + 3)
+        } else if ["http://", "https://", "ftp://"]
+            .iter()
+            .any(|s| starts_with(chars, i, s))
+        {
+            let mut j = i;
+            while j < chars.len()
+                && !chars[j].is_whitespace()
+                && !matches!(chars[j], ')' | '>' | ']' | '}' | '"')
+            {
+                j += 1;
+            }
+            j
+        } else {
+            i += 1;
+            continue;
+        };
+        keep[i..end].fill(false);
+        i = end;
+    }
+}
+
+fn mask_markdown(chars: &[char], keep: &mut [bool]) {
+    let mut i = 0;
+    let mut at_line_start = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            at_line_start = true;
+            i += 1;
+            continue;
+        }
+        if at_line_start && c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let line_start = at_line_start;
+        at_line_start = false;
+
+        let end = if line_start && (starts_with(chars, i, "```") || starts_with(chars, i, "~~~")) {
+            // fenced code block, up to the closing fence
+            let fence: String = chars[i..i + 3].iter().collect();
+            let mut j = line_end(chars, i);
+            loop {
+                if j >= chars.len() {
+                    break chars.len();
+                }
+                let next = j + 1;
+                let mut k = next;
+                while k < chars.len() && (chars[k] == ' ' || chars[k] == '\t') {
+                    k += 1;
+                }
+                if starts_with(chars, k, &fence) {
+                    break line_end(chars, k);
+                }
+                j = line_end(chars, next);
+            }
+        } else if line_start && c == '[' && {
+            let close = group_end(chars, i, '[', ']');
+            chars.get(close) == Some(&':')
+        } {
+            // link reference definition
+            line_end(chars, i)
+        } else if c == '`' {
+            // code span, closed by a backtick run of the same length
+            let mut j = i;
+            while j < chars.len() && chars[j] == '`' {
+                j += 1;
+            }
+            let run: String = chars[i..j].iter().collect();
+            find(chars, j, &run).map_or(j, |k| k + run.len())
+        } else if c == ']' && chars.get(i + 1) == Some(&'(') {
+            // link or image target
+            group_end(chars, i + 1, '(', ')')
+        } else if c == '<'
+            && chars
+                .get(i + 1)
+                .is_some_and(|c| c.is_alphabetic() || *c == '/')
+        {
+            // HTML tags and autolinks
+            let end = line_end(chars, i);
+            find(chars, i, ">")
+                .filter(|j| *j < end)
+                .map_or(i + 1, |j| j + 1)
+        } else if c == '\\' {
+            // escaped punctuation
+            i + 2
+        } else if c == '$' {
+            dollar_end(chars, i).unwrap_or(i + 1)
+        } else {
+            i += 1;
+            continue;
+        };
+        let end = end.min(chars.len());
+        keep[i..end].fill(false);
+        i = end;
+    }
+}
+
+fn mask_latex(chars: &[char], keep: &mut [bool]) {
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let end = if c == '%' {
+            line_end(chars, i)
+        } else if c == '$' {
+            dollar_end(chars, i).unwrap_or(i + 1)
+        } else if starts_with(chars, i, "\\(") {
+            find(chars, i, "\\)").map_or(chars.len(), |j| j + 2)
+        } else if starts_with(chars, i, "\\[") {
+            find(chars, i, "\\]").map_or(chars.len(), |j| j + 2)
+        } else if c == '\\' && chars.get(i + 1).is_some_and(|c| c.is_ascii_alphabetic()) {
+            let mut j = i + 1;
+            while j < chars.len() && chars[j].is_ascii_alphabetic() {
+                j += 1;
+            }
+            let name: String = chars[i + 1..j].iter().collect();
+            if chars.get(j) == Some(&'*') {
+                j += 1;
+            }
+            // optional arguments
+            while chars.get(j) == Some(&'[') {
+                j = group_end(chars, j, '[', ']');
+            }
+            if let Some((_, count)) = COMMANDS.iter().find(|(n, _)| *n == name) {
+                for _ in 0..*count {
+                    if chars.get(j) == Some(&'{') {
+                        let arg_end = group_end(chars, j, '{', '}');
+                        let environment: String = chars[j + 1..arg_end - 1].iter().collect();
+                        j = arg_end;
+                        // skip the whole environment if it is not prose
+                        if name == "begin"
+                            && (MATH_ENVIRONMENTS.contains(&environment.as_str())
+                                || CODE_ENVIRONMENTS.contains(&environment.as_str()))
+                        {
+                            let close = format!("\\end{{{}}}", environment);
+                            j = find(chars, j, &close).map_or(chars.len(), |k| k + close.len());
+                        }
+                    }
+                }
+            }
+            j
+        } else if c == '\\' {
+            // escaped characters, control symbols and line breaks
+            i + 2
+        } else {
+            i += 1;
+            continue;
+        };
+        let end = end.min(chars.len());
+        keep[i..end].fill(false);
+        i = end;
+    }
+}
+
+// ideographs are words on their own, as CJK text has no spaces
+fn is_ideograph(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}')
+}
+
+// FNV-1a folded into a kind, so that words spread over all kinds but those
+// of fallback tokens
+fn word_kind(word: &str) -> Kind {
+    (stable_hash(word.as_bytes()) % fallback::FIRST_KIND as u64) as Kind
+}
+
+pub fn tokenize_str(content: &str, format: Format, stem: bool) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut keep = vec![true; chars.len()];
+    mask_common(&chars, &mut keep);
+    match format {
+        Format::Plain => {}
+        Format::Markdown => mask_markdown(&chars, &mut keep),
+        Format::Latex => mask_latex(&chars, &mut keep),
+    }
+
+    let stemmer = Stemmer::create(Algorithm::English);
+    let mut res: Vec<Token> = vec![];
+    let mut pos = Position::new();
+    let mut last = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if !keep[i] || !c.is_alphanumeric() {
+            i += 1;
+            continue;
+        }
+
+        let mut j = i + 1;
+        if !is_ideograph(c) {
+            while j < chars.len() && keep[j] && !is_ideograph(chars[j]) {
+                if chars[j].is_alphanumeric() {
+                    j += 1;
+                } else if (chars[j] == '\'' || chars[j] == '’')
+                    && chars.get(j + 1).is_some_and(|c| c.is_alphabetic())
+                    && keep.get(j + 1) == Some(&true)
+                {
+                    // contractions and possessives
+                    j += 2;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        let spelling: String = chars[i..j].iter().collect();
+        let folded = spelling.to_lowercase().replace('’', "'");
+        let word = if stem {
+            stemmer.stem(&folded).into_owned()
+        } else {
+            folded
+        };
+        pos.advance(&chars[last..i]);
+        let mut end = pos;
+        end.advance(&chars[i..j]);
+        res.push(pos.token(&end, word_kind(&word), spelling));
+        last = i;
+        i = j;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize_str, Format};
+
+    #[test]
+    fn test_tokenize() {
+        let text = "# Title\n\nSome *bold* [link](http://example.com) `code` and $x^2$.\n```\nfenced\n```\nThe END";
+        let tokens = tokenize_str(text, Format::Markdown, false).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "Title");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 3);
+
+        assert_eq!(tokens[2].spelling, "bold");
+        assert_eq!(tokens[2].line, 3);
+        assert_eq!(tokens[2].column, 7);
+
+        // link target, code and math are dropped
+        assert_eq!(tokens[3].spelling, "link");
+        assert_eq!(tokens[4].spelling, "and");
+        assert_eq!(tokens[4].column, 47);
+
+        // fenced code is dropped, words are case-folded
+        assert_eq!(tokens[5].spelling, "The");
+        assert_eq!(tokens[5].line, 7);
+        assert_eq!(tokens[6].spelling, "END");
+        let end = tokenize_str("end", Format::Plain, false).unwrap();
+        assert_eq!(tokens[6].kind, end[0].kind);
+    }
+
+    #[test]
+    fn test_latex() {
+        let text = "\\section{Intro} % comment\nWe \\emph{show}~\\cite{key} that\n\\begin{equation}\n  E = mc^2\n\\end{equation}\nit's running.";
+        let tokens = tokenize_str(text, Format::Latex, true).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        let spellings: Vec<&str> = tokens.iter().map(|t| t.spelling.as_str()).collect();
+        assert_eq!(
+            spellings,
+            vec!["Intro", "We", "show", "that", "it's", "running"]
+        );
+        assert_eq!(tokens[1].line, 2);
+        assert_eq!(tokens[1].column, 1);
+        assert_eq!(tokens[4].line, 6);
+
+        // stemmed
+        let run = tokenize_str("run", Format::Plain, true).unwrap();
+        assert_eq!(tokens[5].kind, run[0].kind);
+    }
+
+    #[test]
+    fn test_ideographs() {
+        let tokens = tokenize_str("抄袭检测 test", Format::Plain, false).unwrap();
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[1].spelling, "袭");
+        assert_eq!(tokens[1].column, 2);
+        assert_eq!(tokens[4].column, 6);
+    }
+}
@@ -1,18 +1,203 @@
+use super::{
+    fallback::{recover, Syntax},
+    scanner::{LineIndex, Position},
+};
 use crate::lang::Tokenize;
-use crate::token::Token;
-use anyhow::anyhow;
-use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use crate::token::{Diagnostic, Kind, Token, Tokenized};
+use proc_macro2::{Delimiter, LexError, Span, TokenStream, TokenTree};
 use std::str::FromStr;
 
 pub struct Rust;
 
 impl Tokenize for Rust {
-    fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
-        tokenize_str(content)
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        // unbalanced delimiters would fail everything after them, so they
+        // are fixed before the remaining errors are recovered from by line
+        let (_, mut diagnostics) = balance(content);
+        let mut res = recover(content, &SYNTAX, tokenize_balanced);
+        res.diagnostics.append(&mut diagnostics);
+        res.diagnostics.sort_by_key(|d| (d.line, d.column));
+        res
     }
 }
 
-fn flatten(token_stream: TokenStream) -> Vec<Token> {
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"'],
+};
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// end of a string literal whose opening quote is at `i`
+fn string_end(chars: &[char], i: usize) -> usize {
+    let mut j = i + 1;
+    while j < chars.len() && chars[j] != '"' {
+        j += if chars[j] == '\\' { 2 } else { 1 };
+    }
+    (j + 1).min(chars.len())
+}
+
+// end of a raw string literal whose first `#` or quote is at `i`
+fn raw_string_end(chars: &[char], i: usize) -> Option<usize> {
+    let hashes = chars[i..].iter().take_while(|c| **c == '#').count();
+    if chars.get(i + hashes) != Some(&'"') {
+        return None;
+    }
+    let mut j = i + hashes + 1;
+    while j < chars.len() {
+        if chars[j] == '"' && chars[j + 1..].iter().take_while(|c| **c == '#').count() >= hashes {
+            return Some(j + 1 + hashes);
+        }
+        j += 1;
+    }
+    Some(chars.len())
+}
+
+/// Drop closing delimiters without an opening one and opening ones closed by
+/// another kind, then close the rest at the end, past the last line. Columns
+/// are kept, as dropped delimiters become spaces.
+fn balance(content: &str) -> (String, Vec<Diagnostic>) {
+    let mut chars: Vec<char> = content.chars().collect();
+    let mut diagnostics = vec![];
+    // opening delimiters: index, delimiter, line, column
+    let mut stack: Vec<(usize, char, u32, u32)> = vec![];
+    let mut pos = Position::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let mut end = i + 1;
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while end < chars.len() && chars[end] != '\n' {
+                end += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            // block comments nest
+            let mut depth = 0;
+            end = i;
+            while end < chars.len() {
+                if chars[end] == '/' && chars.get(end + 1) == Some(&'*') {
+                    depth += 1;
+                    end += 2;
+                } else if chars[end] == '*' && chars.get(end + 1) == Some(&'/') {
+                    depth -= 1;
+                    end += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    end += 1;
+                }
+            }
+            end = end.min(chars.len());
+        } else if c == '"' {
+            end = string_end(&chars, i);
+        } else if c == '\'' {
+            // character literals, but not lifetimes
+            if chars.get(i + 1) == Some(&'\\') {
+                end = i + 2;
+                while end < chars.len() && chars[end] != '\'' && chars[end] != '\n' {
+                    end += 1;
+                }
+                end = (end + 1).min(chars.len());
+            } else if chars.get(i + 2) == Some(&'\'') {
+                end = i + 3;
+            }
+        } else if is_identifier_char(c) {
+            while end < chars.len() && is_identifier_char(chars[end]) {
+                end += 1;
+            }
+            let word: String = chars[i..end].iter().collect();
+            if matches!(word.as_str(), "r" | "br" | "cr") {
+                if let Some(raw_end) = raw_string_end(&chars, end) {
+                    end = raw_end;
+                }
+            }
+        } else if matches!(c, '(' | '[' | '{') {
+            stack.push((i, c, pos.line, pos.column));
+        } else if matches!(c, ')' | ']' | '}') {
+            let open = match c {
+                ')' => '(',
+                ']' => '[',
+                _ => '{',
+            };
+            if stack.iter().any(|(_, delimiter, _, _)| *delimiter == open) {
+                while let Some((index, delimiter, line, column)) = stack.pop() {
+                    if delimiter == open {
+                        break;
+                    }
+                    diagnostics.push(Diagnostic {
+                        line,
+                        column,
+                        message: format!("unclosed delimiter `{}`", delimiter),
+                    });
+                    chars[index] = ' ';
+                }
+            } else {
+                diagnostics.push(Diagnostic {
+                    line: pos.line,
+                    column: pos.column,
+                    message: format!("unexpected closing delimiter `{}`", c),
+                });
+                chars[i] = ' ';
+            }
+        }
+        pos.advance(&chars[i..end]);
+        i = end;
+    }
+
+    let mut res: String = chars.into_iter().collect();
+    if !stack.is_empty() {
+        res.push('\n');
+    }
+    for (_, delimiter, line, column) in stack.into_iter().rev() {
+        diagnostics.push(Diagnostic {
+            line,
+            column,
+            message: format!("unclosed delimiter `{}`", delimiter),
+        });
+        res.push(match delimiter {
+            '(' => ')',
+            '[' => ']',
+            _ => '}',
+        });
+    }
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    (res, diagnostics)
+}
+
+fn lex_error(err: LexError) -> Diagnostic {
+    let start = err.span().start();
+    Diagnostic {
+        line: start.line as u32,
+        column: start.column as u32 + 1,
+        message: err.to_string(),
+    }
+}
+
+// tokens of `content` with balanced delimiters
+fn tokenize_balanced(content: &str) -> anyhow::Result<Vec<Token>> {
+    let (balanced, _) = balance(content);
+    let token_stream = TokenStream::from_str(&balanced).map_err(lex_error)?;
+    // without the delimiters added past the last line
+    let lines = content.lines().count() as u32;
+    Ok(flatten(&LineIndex::new(&balanced), token_stream)
+        .into_iter()
+        .filter(|token| token.line <= lines)
+        .collect())
+}
+
+// proc-macro2 columns are 0-based, in characters
+fn token(lines: &LineIndex, span: Span, kind: Kind, spelling: String) -> Token {
+    let (start, end) = (span.start(), span.end());
+    let bytes = lines.byte(start.line as u32, start.column as u32 + 1)
+        ..lines.byte(end.line as u32, end.column as u32 + 1);
+    lines.token(kind, spelling, bytes)
+}
+
+fn flatten(lines: &LineIndex, token_stream: TokenStream) -> Vec<Token> {
     let mut res = vec![];
     // https://doc.rust-lang.org/reference/keywords.html
     let keywords = [
@@ -32,46 +217,36 @@ fn flatten(token_stream: TokenStream) -> Vec<Token> {
                     Delimiter::Bracket => ("[", "]"),
                     Delimiter::None => continue,
                 };
-                res.push(Token {
-                    spelling: spelling.0.to_string(),
-                    kind: group.delimiter() as u8,
-                    line: group.span_open().start().line as u32,
-                    column: group.span_open().start().column as u32 + 1,
-                });
-                res.extend(flatten(group.stream()));
-                res.push(Token {
-                    spelling: spelling.1.to_string(),
-                    kind: group.delimiter() as u8,
-                    line: group.span_close().start().line as u32,
-                    column: group.span_close().start().column as u32 + 1,
-                });
+                res.push(token(
+                    lines,
+                    group.span_open(),
+                    group.delimiter() as Kind,
+                    spelling.0.to_string(),
+                ));
+                res.extend(flatten(lines, group.stream()));
+                res.push(token(
+                    lines,
+                    group.span_close(),
+                    group.delimiter() as Kind,
+                    spelling.1.to_string(),
+                ));
             }
             TokenTree::Literal(literal) => {
                 // kind: 3
-                res.push(Token {
-                    spelling: format!("{}", literal),
-                    kind: 3,
-                    line: literal.span().start().line as u32,
-                    column: literal.span().start().column as u32 + 1,
-                });
+                res.push(token(lines, literal.span(), 3, format!("{}", literal)));
             }
             TokenTree::Ident(ident) => {
                 // kind: [4, 4+keywords.len()]
                 let spelling = format!("{}", ident);
                 if let Some(i) = keywords.iter().position(|s| s == &spelling) {
-                    res.push(Token {
-                        spelling: format!("{}", ident),
-                        kind: 5 + i as u8,
-                        line: ident.span().start().line as u32,
-                        column: ident.span().start().column as u32 + 1,
-                    });
+                    res.push(token(
+                        lines,
+                        ident.span(),
+                        5 + i as Kind,
+                        format!("{}", ident),
+                    ));
                 } else {
-                    res.push(Token {
-                        spelling: format!("{}", ident),
-                        kind: 4,
-                        line: ident.span().start().line as u32,
-                        column: ident.span().start().column as u32 + 1,
-                    });
+                    res.push(token(lines, ident.span(), 4, format!("{}", ident)));
                 }
             }
             TokenTree::Punct(punct) => {
@@ -81,14 +256,12 @@ fn flatten(token_stream: TokenStream) -> Vec<Token> {
                     continue;
                 }
 
-                res.push(Token {
-                    spelling: format!("{}", punct),
-                    kind: 5
-                        + keywords.len() as u8
-                        + (punct.as_char() as u8) % (251 - keywords.len() as u8),
-                    line: punct.span().start().line as u32,
-                    column: punct.span().start().column as u32 + 1,
-                });
+                res.push(token(
+                    lines,
+                    punct.span(),
+                    5 + keywords.len() as Kind + punct.as_char() as Kind,
+                    format!("{}", punct),
+                ));
             }
         }
     }
@@ -96,8 +269,8 @@ fn flatten(token_stream: TokenStream) -> Vec<Token> {
 }
 
 pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
-    let token_stream = TokenStream::from_str(content).map_err(|err| anyhow!("{}", err))?;
-    Ok(flatten(token_stream))
+    let token_stream = TokenStream::from_str(content).map_err(lex_error)?;
+    Ok(flatten(&LineIndex::new(content), token_stream))
 }
 
 #[cfg(test)]
@@ -152,4 +325,45 @@ mod tests {
         assert_eq!(tokens[0].line, 2);
         assert_eq!(tokens[0].column, 1);
     }
+
+    #[test]
+    fn test_unbalanced() {
+        use crate::lang::Tokenize;
+
+        let code = "fn main() {\n    let x = (1 + 2;\n}\n}\nfn f() {\n    g(\"a)\");";
+        assert!(tokenize_str(code).is_err());
+        let tokenized = super::Rust.tokenize_str(code);
+
+        eprintln!("{:?}", tokenized);
+
+        let messages: Vec<String> = tokenized
+            .diagnostics
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "L2:13: unclosed delimiter `(`",
+                "L4:1: unexpected closing delimiter `}`",
+                "L5:8: unclosed delimiter `{`",
+            ]
+        );
+
+        // everything else is kept, with delimiters in strings left alone
+        let spellings: Vec<&str> = tokenized
+            .tokens
+            .iter()
+            .map(|t| t.spelling.as_str())
+            .collect();
+        assert_eq!(
+            spellings,
+            [
+                "fn", "main", "(", ")", "{", "let", "x", "=", "1", "+", "2", "}", "fn", "f", "(",
+                ")", "{", "g", "(", "\"a)\"", ")"
+            ]
+        );
+        assert_eq!(tokenized.tokens[20].line, 6);
+        assert_eq!(tokenized.tokens[20].column, 11);
+    }
 }
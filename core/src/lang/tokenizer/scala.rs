@@ -0,0 +1,157 @@
+use super::{fallback::Syntax, grammar::tokenized, grammar::Grammar};
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+use tree_sitter::Tree;
+
+#[derive(Default)]
+pub struct Scala {
+    /// Treat the Chisel hardware construction API as keywords
+    pub chisel: bool,
+}
+
+impl Tokenize for Scala {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        tokenized(content, &GRAMMAR.syntax, GRAMMAR.parse(content), |tree| {
+            tokenize_tree(content, tree, self.chisel)
+        })
+    }
+}
+
+// common operator identifiers, including Chisel ones, others share a single kind
+const OPERATORS: [&str; 33] = [
+    "+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "&", "|", "^", "!", "~",
+    "<<", ">>", "::", "++", "+=", "-=", ":=", "<>", "===", "=/=", "##", "+&", "-&", "+%", "-%",
+];
+
+// Chisel constructors, types and control flow
+const CHISEL_KEYWORDS: [&str; 38] = [
+    "Module",
+    "IO",
+    "Input",
+    "Output",
+    "Flipped",
+    "Bundle",
+    "Wire",
+    "WireInit",
+    "WireDefault",
+    "Reg",
+    "RegInit",
+    "RegNext",
+    "RegEnable",
+    "Mem",
+    "SyncReadMem",
+    "Vec",
+    "VecInit",
+    "UInt",
+    "SInt",
+    "Bool",
+    "U",
+    "S",
+    "B",
+    "W",
+    "when",
+    "elsewhen",
+    "otherwise",
+    "switch",
+    "is",
+    "Mux",
+    "MuxCase",
+    "MuxLookup",
+    "Cat",
+    "Fill",
+    "Decoupled",
+    "Valid",
+    "ChiselEnum",
+    "DontCare",
+];
+
+pub static GRAMMAR: Grammar = Grammar::new(
+    "Scala",
+    tree_sitter_scala::language,
+    &["comment", "block_comment"],
+    &["identifier", "type_identifier"],
+    &[
+        &["integer_literal", "floating_point_literal"],
+        // interpolations included
+        &["string", "interpolated_string_expression"],
+    ],
+    Syntax {
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        quotes: &['"', '\''],
+    },
+);
+
+pub fn tokenize_str(content: &str, chisel: bool) -> anyhow::Result<Vec<Token>> {
+    Ok(tokenize_tree(content, &GRAMMAR.parse(content)?, chisel))
+}
+
+// kinds past the grammar's: common operators, then the Chisel API
+fn tokenize_tree(content: &str, tree: &Tree, chisel: bool) -> Vec<Token> {
+    let operator_base = GRAMMAR.kind_count() as Kind;
+    let chisel_base = operator_base + OPERATORS.len() as Kind;
+    GRAMMAR.tokenize_tree_with(content, tree, |node, text| match node.kind() {
+        "operator_identifier" => match OPERATORS.iter().position(|op| *op == text) {
+            Some(index) => Some(operator_base + index as Kind),
+            None => GRAMMAR.kind(node),
+        },
+        "identifier" | "type_identifier" if chisel => {
+            match CHISEL_KEYWORDS.iter().position(|k| *k == text) {
+                Some(index) => Some(chisel_base + index as Kind),
+                None => GRAMMAR.kind(node),
+            }
+        }
+        _ => GRAMMAR.kind(node),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize_str;
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "object Main {\n  // entry\n  def main(args: Array[String]): Unit = {\n    println(s\"Hello, ${args.length}\")\n  }\n}";
+        let tokens = tokenize_str(code, false).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "object");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[1].spelling, "Main");
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(tokens[1].column, 8);
+
+        // comment is skipped
+        assert_eq!(tokens[3].spelling, "def");
+        assert_eq!(tokens[3].line, 3);
+        assert_eq!(tokens[3].column, 3);
+
+        // interpolated string is a single token
+        assert_eq!(tokens[19].spelling, "s\"Hello, ${args.length}\"");
+        assert_eq!(tokens[19].line, 4);
+        assert_eq!(tokens[19].column, 13);
+    }
+
+    #[test]
+    fn test_chisel() {
+        let code = "class Counter extends Module {\n  val io = IO(new Bundle { val out = Output(UInt(8.W)) })\n  val count = RegInit(0.U(8.W))\n  when (io.en) { count := count + 1.U }\n}";
+        let plain = tokenize_str(code, false).unwrap();
+        let chisel = tokenize_str(code, true).unwrap();
+        let plain_kind: Vec<Kind> = plain.iter().map(|t| t.kind).collect();
+        let chisel_kind: Vec<Kind> = chisel.iter().map(|t| t.kind).collect();
+        assert_eq!(plain.len(), chisel.len());
+        assert_ne!(plain_kind, chisel_kind);
+
+        // Chisel API calls are distinguished from each other but not renamed variables
+        let renamed = tokenize_str(&code.replace("count", "cnt"), true).unwrap();
+        let renamed_kind: Vec<Kind> = renamed.iter().map(|t| t.kind).collect();
+        assert_eq!(chisel_kind, renamed_kind);
+        let reg = tokenize_str(&code.replace("RegInit", "WireInit"), true).unwrap();
+        let reg_kind: Vec<Kind> = reg.iter().map(|t| t.kind).collect();
+        assert_ne!(chisel_kind, reg_kind);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::token::{Kind, Token};
+use std::ops::Range;
+
+/// Line and column tracking for hand-written lexers
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Position {
+    // 1-based, in characters
+    pub line: u32,
+    pub column: u32,
+    // 0-based
+    pub byte: usize,
+}
+
+impl Position {
+    pub fn new() -> Self {
+        Self {
+            line: 1,
+            column: 1,
+            byte: 0,
+        }
+    }
+
+    /// Advance over `chars`
+    pub fn advance(&mut self, chars: &[char]) {
+        for c in chars {
+            if *c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+            self.byte += c.len_utf8();
+        }
+    }
+
+    /// Token from here up to `end`
+    pub fn token(&self, end: &Position, kind: Kind, spelling: String) -> Token {
+        Token {
+            kind,
+            spelling,
+            line: self.line,
+            column: self.column,
+            end_line: end.line,
+            end_column: end.column,
+            bytes: self.byte..end.byte,
+        }
+    }
+}
+
+/// Positions of byte offsets, and the other way round, for lexers that only
+/// report one of them
+pub(crate) struct LineIndex<'a> {
+    pub content: &'a str,
+    // byte offsets of line starts
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(content: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(content.match_indices('\n').map(|(i, _)| i + 1));
+        Self { content, starts }
+    }
+
+    /// Position of a byte offset, clamped to the content
+    pub fn position(&self, byte: usize) -> Position {
+        let mut byte = byte.min(self.content.len());
+        while !self.content.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        let line = self.starts.partition_point(|start| *start <= byte) - 1;
+        let column = self.content[self.starts[line]..byte].chars().count();
+        Position {
+            line: line as u32 + 1,
+            column: column as u32 + 1,
+            byte,
+        }
+    }
+
+    /// Byte offset of a 1-based line and character column, clamped to the
+    /// line
+    pub fn byte(&self, line: u32, column: u32) -> usize {
+        let line = (line.max(1) as usize - 1).min(self.starts.len() - 1);
+        let start = self.starts[line];
+        let end = self
+            .starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.content.len());
+        self.content[start..end]
+            .char_indices()
+            .nth(column.max(1) as usize - 1)
+            .map_or(end, |(i, _)| start + i)
+    }
+
+    /// Token covering `bytes` of the content
+    pub fn token(&self, kind: Kind, spelling: String, bytes: Range<usize>) -> Token {
+        self.position(bytes.start)
+            .token(&self.position(bytes.end), kind, spelling)
+    }
+
+    /// Byte offset of a slice of the content, e.g. a token's spelling
+    pub fn offset(&self, slice: &str) -> usize {
+        slice.as_ptr() as usize - self.content.as_ptr() as usize
+    }
+}
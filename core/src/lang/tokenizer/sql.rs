@@ -1,22 +1,236 @@
+use super::fallback::{recover, Syntax};
+use super::scanner::LineIndex;
 use crate::lang::Tokenize;
-use crate::token::Token;
-use sqlparser::{dialect::GenericDialect, tokenizer::Token::*, tokenizer::Tokenizer};
+use crate::token::{Diagnostic, Kind, Token, Tokenized};
+use sqlparser::{
+    dialect::{GenericDialect, PostgreSqlDialect, SQLiteDialect},
+    tokenizer::Token::*,
+    tokenizer::{TokenWithLocation, Tokenizer, Word},
+};
 
-pub struct SQL;
+/// Decides how strings and quoted identifiers are lexed and which keywords
+/// are reserved
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Generic,
+    PostgreSql,
+    SQLite,
+}
+
+#[derive(Default)]
+pub struct SQL {
+    pub dialect: Dialect,
+}
 
 impl Tokenize for SQL {
-    fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
-        tokenize_str(content)
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, |content| {
+            tokenize_str(content, self.dialect)
+        })
+    }
+}
+
+// keywords reserved by the SQL standard, and LIMIT, used by all dialects
+const COMMON_KEYWORDS: [&str; 97] = [
+    "ALL",
+    "ALTER",
+    "AND",
+    "ANY",
+    "ARRAY",
+    "AS",
+    "ASC",
+    "ASYMMETRIC",
+    "BEGIN",
+    "BETWEEN",
+    "BOTH",
+    "BY",
+    "CASE",
+    "CAST",
+    "CHECK",
+    "COLLATE",
+    "COLUMN",
+    "COMMIT",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "CURRENT_DATE",
+    "CURRENT_ROLE",
+    "CURRENT_TIME",
+    "CURRENT_TIMESTAMP",
+    "CURRENT_USER",
+    "DEFAULT",
+    "DELETE",
+    "DESC",
+    "DISTINCT",
+    "DROP",
+    "ELSE",
+    "END",
+    "ESCAPE",
+    "EXCEPT",
+    "EXISTS",
+    "FALSE",
+    "FETCH",
+    "FOR",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "GRANT",
+    "GROUP",
+    "HAVING",
+    "IN",
+    "INNER",
+    "INSERT",
+    "INTERSECT",
+    "INTO",
+    "IS",
+    "JOIN",
+    "LATERAL",
+    "LEADING",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "LOCALTIME",
+    "LOCALTIMESTAMP",
+    "NATURAL",
+    "NOT",
+    "NULL",
+    "OFFSET",
+    "ON",
+    "ONLY",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "OVERLAPS",
+    "PARTITION",
+    "PRIMARY",
+    "RECURSIVE",
+    "REFERENCES",
+    "RIGHT",
+    "ROLLBACK",
+    "SELECT",
+    "SESSION_USER",
+    "SET",
+    "SOME",
+    "SYMMETRIC",
+    "TABLE",
+    "TABLESAMPLE",
+    "THEN",
+    "TO",
+    "TRAILING",
+    "TRUE",
+    "UNION",
+    "UNIQUE",
+    "UPDATE",
+    "USER",
+    "USING",
+    "VALUES",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+];
+
+// not reserved by the standard, but by PostgreSQL
+const POSTGRESQL_KEYWORDS: [&str; 18] = [
+    "ANALYSE",
+    "ANALYZE",
+    "AUTHORIZATION",
+    "CONCURRENTLY",
+    "CURRENT_CATALOG",
+    "CURRENT_SCHEMA",
+    "DEFERRABLE",
+    "DO",
+    "FREEZE",
+    "ILIKE",
+    "INITIALLY",
+    "ISNULL",
+    "NOTNULL",
+    "PLACING",
+    "RETURNING",
+    "SIMILAR",
+    "VARIADIC",
+    "VERBOSE",
+];
+
+// not reserved by the standard, but by SQLite, which takes only few keywords
+// as identifiers
+const SQLITE_KEYWORDS: [&str; 12] = [
+    "AUTOINCREMENT",
+    "GLOB",
+    "INDEX",
+    "INDEXED",
+    "ISNULL",
+    "NOTNULL",
+    "PRAGMA",
+    "REGEXP",
+    "TRANSACTION",
+    "TRIGGER",
+    "VACUUM",
+    "VIEW",
+];
+
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['\'', '"'],
+};
+
+// keywords are case-insensitive, unless quoted
+fn keyword(word: &Word, dialect: Dialect) -> Option<String> {
+    let upper = word.value.to_ascii_uppercase();
+    let reserved = COMMON_KEYWORDS.contains(&upper.as_str())
+        || match dialect {
+            Dialect::Generic => false,
+            Dialect::PostgreSql => POSTGRESQL_KEYWORDS.contains(&upper.as_str()),
+            Dialect::SQLite => SQLITE_KEYWORDS.contains(&upper.as_str()),
+        };
+    (word.quote_style.is_none() && reserved).then_some(upper)
+}
+
+/// Identifier, quoted or not: 1, reserved keyword: [69, 195]
+fn word_kind(word: &Word, dialect: Dialect) -> Kind {
+    match keyword(word, dialect) {
+        // positions in all tables, so that kinds agree across dialects
+        Some(keyword) => {
+            69 + COMMON_KEYWORDS
+                .iter()
+                .chain(&POSTGRESQL_KEYWORDS)
+                .chain(&SQLITE_KEYWORDS)
+                .position(|k| *k == keyword)
+                .unwrap() as Kind
+        }
+        None => 1,
     }
 }
 
-pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
-    let dialect = GenericDialect {};
+pub fn tokenize_str(content: &str, dialect: Dialect) -> anyhow::Result<Vec<Token>> {
+    let parser_dialect: &dyn sqlparser::dialect::Dialect = match dialect {
+        Dialect::Generic => &GenericDialect {},
+        Dialect::PostgreSql => &PostgreSqlDialect {},
+        Dialect::SQLite => &SQLiteDialect {},
+    };
     let mut res = vec![];
-    for token in Tokenizer::new(&dialect, content).tokenize_with_location()? {
+    let tokens = Tokenizer::new(parser_dialect, content)
+        .tokenize_with_location()
+        .map_err(|err| Diagnostic {
+            line: err.location.line as u32,
+            column: err.location.column as u32,
+            message: err.message,
+        })?;
+    let lines = LineIndex::new(content);
+    let start = |i: usize| {
+        tokens
+            .get(i)
+            .map_or(content.len(), |token: &TokenWithLocation| {
+                lines.byte(token.location.line as u32, token.location.column as u32)
+            })
+    };
+    for (i, token) in tokens.iter().enumerate() {
         let kind = match token.token {
             EOF => continue,
-            Word(_) => 1,
+            Word(ref word) => word_kind(word, dialect),
             Number(_, _) => 2,
             Char(_) => 3,
             SingleQuotedString(_) => 4,
@@ -85,26 +299,22 @@ pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
             AtQuestion => 67,
             AtAt => 68,
         };
-        res.push(Token {
-            kind,
-            spelling: token.to_string(),
-            line: token.location.line as u32,
-            column: token.location.column as u32,
-        });
+        // whitespace is a token too, so this one ends where the next starts
+        res.push(lines.token(kind, token.to_string(), start(i)..start(i + 1)));
     }
     Ok(res)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::tokenize_str;
+    use super::{tokenize_str, Dialect};
 
     #[test]
     fn test_tokenize() {
         // example taken from https://crates.io/crates/sqlparser
         let code =
             "SELECT a, b, 123, myfunc(b)\nFROM table_1\nWHERE a > b AND b < 100\nORDER BY a DESC, b";
-        let tokens = tokenize_str(code).unwrap();
+        let tokens = tokenize_str(code, Dialect::Generic).unwrap();
 
         eprintln!("{:?}", tokens);
 
@@ -128,4 +338,45 @@ mod tests {
         assert_eq!(tokens[13].line, 3);
         assert_eq!(tokens[13].column, 1);
     }
+
+    #[test]
+    fn test_kinds() {
+        let code = "SELECT name FROM t JOIN u\nselect \"name\" from T join U";
+        let tokens = tokenize_str(code, Dialect::Generic).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        // keywords are distinct, whatever the case
+        assert_ne!(tokens[0].kind, tokens[2].kind);
+        assert_ne!(tokens[0].kind, tokens[4].kind);
+        for i in 0..6 {
+            assert_eq!(tokens[i].kind, tokens[i + 6].kind);
+        }
+        // identifiers collapse, quoted or not
+        assert_eq!(tokens[1].kind, 1);
+        assert_eq!(tokens[7].spelling, "\"name\"");
+        assert_eq!(tokens[3].kind, 1);
+    }
+
+    #[test]
+    fn test_dialect() {
+        let code = "SELECT * FROM t WHERE a ILIKE 'x%' AND b GLOB [c]";
+        let generic = tokenize_str(code, Dialect::Generic).unwrap();
+        let postgresql = tokenize_str(code, Dialect::PostgreSql).unwrap();
+        let sqlite = tokenize_str(code, Dialect::SQLite).unwrap();
+
+        eprintln!("{:?}", sqlite);
+
+        assert_eq!(generic[6].spelling, "ILIKE");
+        assert_eq!(generic[6].kind, 1);
+        assert_ne!(postgresql[6].kind, 1);
+        assert_eq!(sqlite[6].kind, 1);
+
+        assert_eq!(postgresql[10].spelling, "GLOB");
+        assert_eq!(postgresql[10].kind, 1);
+        assert_ne!(sqlite[10].kind, 1);
+        // a quoted identifier in SQLite
+        assert_eq!(sqlite[11].spelling, "[c]");
+        assert_eq!(sqlite[11].kind, 1);
+    }
 }
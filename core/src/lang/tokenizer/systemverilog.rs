@@ -0,0 +1,559 @@
+use super::{
+    fallback::{recover, Syntax},
+    scanner::Position,
+};
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+
+pub struct SystemVerilog;
+
+impl Tokenize for SystemVerilog {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, tokenize_str)
+    }
+}
+
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"'],
+};
+
+// IEEE 1800-2017 keywords used in design and testbench code
+const KEYWORDS: [&str; 150] = [
+    "alias",
+    "always",
+    "always_comb",
+    "always_ff",
+    "always_latch",
+    "and",
+    "assert",
+    "assign",
+    "assume",
+    "automatic",
+    "before",
+    "begin",
+    "bind",
+    "bit",
+    "break",
+    "buf",
+    "byte",
+    "case",
+    "casex",
+    "casez",
+    "chandle",
+    "class",
+    "clocking",
+    "const",
+    "constraint",
+    "context",
+    "continue",
+    "cover",
+    "deassign",
+    "default",
+    "defparam",
+    "disable",
+    "do",
+    "edge",
+    "else",
+    "end",
+    "endcase",
+    "endclass",
+    "endclocking",
+    "endfunction",
+    "endgenerate",
+    "endinterface",
+    "endmodule",
+    "endpackage",
+    "endprogram",
+    "endproperty",
+    "endsequence",
+    "endtask",
+    "enum",
+    "event",
+    "export",
+    "extends",
+    "extern",
+    "final",
+    "for",
+    "force",
+    "foreach",
+    "forever",
+    "fork",
+    "function",
+    "generate",
+    "genvar",
+    "if",
+    "iff",
+    "implements",
+    "import",
+    "initial",
+    "inout",
+    "input",
+    "inside",
+    "int",
+    "integer",
+    "interface",
+    "join",
+    "join_any",
+    "join_none",
+    "let",
+    "local",
+    "localparam",
+    "logic",
+    "longint",
+    "modport",
+    "module",
+    "nand",
+    "negedge",
+    "new",
+    "nor",
+    "not",
+    "null",
+    "or",
+    "output",
+    "package",
+    "packed",
+    "parameter",
+    "posedge",
+    "priority",
+    "program",
+    "property",
+    "protected",
+    "pure",
+    "rand",
+    "randc",
+    "randcase",
+    "real",
+    "realtime",
+    "ref",
+    "reg",
+    "release",
+    "repeat",
+    "restrict",
+    "return",
+    "sequence",
+    "shortint",
+    "shortreal",
+    "signed",
+    "static",
+    "string",
+    "struct",
+    "super",
+    "task",
+    "this",
+    "time",
+    "timeprecision",
+    "timeunit",
+    "tri",
+    "type",
+    "typedef",
+    "union",
+    "unique",
+    "unique0",
+    "unsigned",
+    "var",
+    "virtual",
+    "void",
+    "wait",
+    "wait_order",
+    "while",
+    "wire",
+    "with",
+    "xnor",
+    "xor",
+    "supply0",
+    "supply1",
+    "wand",
+    "wor",
+    "uwire",
+    "triand",
+    "trior",
+    "tri0",
+    "tri1",
+];
+
+// IEEE 1800-2017 keywords for assertions, coverage, configurations and
+// switch-level modeling, whose kinds come after all others
+const RARE_KEYWORDS: [&str; 98] = [
+    "accept_on",
+    "binsof",
+    "bins",
+    "bufif0",
+    "bufif1",
+    "cell",
+    "checker",
+    "cmos",
+    "config",
+    "covergroup",
+    "coverpoint",
+    "cross",
+    "design",
+    "dist",
+    "endchecker",
+    "endconfig",
+    "endgroup",
+    "endprimitive",
+    "endspecify",
+    "endtable",
+    "eventually",
+    "expect",
+    "first_match",
+    "forkjoin",
+    "global",
+    "highz0",
+    "highz1",
+    "ifnone",
+    "ignore_bins",
+    "illegal_bins",
+    "implies",
+    "incdir",
+    "include",
+    "instance",
+    "interconnect",
+    "intersect",
+    "large",
+    "liblist",
+    "library",
+    "macromodule",
+    "matches",
+    "medium",
+    "nettype",
+    "nexttime",
+    "nmos",
+    "noshowcancelled",
+    "notif0",
+    "notif1",
+    "pmos",
+    "primitive",
+    "pull0",
+    "pull1",
+    "pulldown",
+    "pullup",
+    "pulsestyle_ondetect",
+    "pulsestyle_onevent",
+    "randsequence",
+    "rcmos",
+    "reject_on",
+    "rnmos",
+    "rpmos",
+    "rtran",
+    "rtranif0",
+    "rtranif1",
+    "s_always",
+    "s_eventually",
+    "s_nexttime",
+    "s_until",
+    "s_until_with",
+    "scalared",
+    "showcancelled",
+    "small",
+    "soft",
+    "solve",
+    "specify",
+    "specparam",
+    "strong",
+    "strong0",
+    "strong1",
+    "sync_accept_on",
+    "sync_reject_on",
+    "table",
+    "tagged",
+    "throughout",
+    "tran",
+    "tranif0",
+    "tranif1",
+    "trireg",
+    "until",
+    "until_with",
+    "untyped",
+    "use",
+    "vectored",
+    "weak",
+    "weak0",
+    "weak1",
+    "wildcard",
+    "within",
+];
+
+// longer operators first, so that the longest one matches
+const OPERATORS: [&str; 71] = [
+    "<<<=", ">>>=", "===", "!==", "==?", "!=?", "<<<", ">>>", "<<=", ">>=", "<->", "|->", "|=>",
+    "->>", "**", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "==", "!=", "<=",
+    ">=", "&&", "||", "~&", "~|", "~^", "^~", "<<", ">>", "::", "->", "=>", "*>", "##", "@@", "'{",
+    ".*", "+", "-", "*", "/", "%", "=", "<", ">", "!", "&", "|", "^", "~", "?", ":", "(", ")", "[",
+    "]", "{", "}", ";", ",", ".", "#", "@",
+];
+
+// kind: 0 for identifiers, [1, 4] for literals and special names, 5 unused
+const NUMBER: Kind = 1;
+const STRING: Kind = 2;
+const SYSTEM_NAME: Kind = 3;
+const DIRECTIVE: Kind = 4;
+// kind: [6, 6+KEYWORDS.len()) for keywords, then operators
+const KEYWORD_BASE: Kind = 6;
+const OPERATOR_BASE: Kind = KEYWORD_BASE + KEYWORDS.len() as Kind;
+const TICK: Kind = OPERATOR_BASE + OPERATORS.len() as Kind;
+const UNKNOWN: Kind = TICK + 1;
+const RARE_KEYWORD_BASE: Kind = UNKNOWN + 1;
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+fn is_based_digit(c: char) -> bool {
+    c.is_ascii_hexdigit() || matches!(c, '_' | 'x' | 'X' | 'z' | 'Z' | '?')
+}
+
+// scan a based number after the tick, e.g. `sh1F` in `8'sh1F`, returning its end
+fn scan_based(chars: &[char], mut j: usize) -> Option<usize> {
+    if matches!(chars.get(j), Some('s' | 'S')) {
+        j += 1;
+    }
+    if !matches!(
+        chars.get(j),
+        Some('b' | 'B' | 'o' | 'O' | 'd' | 'D' | 'h' | 'H')
+    ) {
+        return None;
+    }
+    j += 1;
+    while j < chars.len() && (chars[j] == ' ' || chars[j] == '\t') {
+        j += 1;
+    }
+    let start = j;
+    while j < chars.len() && is_based_digit(chars[j]) {
+        j += 1;
+    }
+    if j == start {
+        None
+    } else {
+        Some(j)
+    }
+}
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut res = vec![];
+    let mut i = 0;
+    let mut pos = Position::new();
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // drop whitespace
+        if c.is_whitespace() {
+            pos.advance(&chars[i..i + 1]);
+            i += 1;
+            continue;
+        }
+
+        // drop comments
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            let mut end = i;
+            while end < chars.len() && chars[end] != '\n' {
+                end += 1;
+            }
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let mut end = i + 2;
+            while end + 1 < chars.len() && !(chars[end] == '*' && chars[end + 1] == '/') {
+                end += 1;
+            }
+            let end = (end + 2).min(chars.len());
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        let end;
+        let kind;
+        if c.is_ascii_digit() {
+            // decimal, real or time literal: 1_000, 3.14, 1e-3, 10ns
+            let mut j = i;
+            while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                j += 1;
+            }
+            if j + 1 < chars.len() && chars[j] == '.' && chars[j + 1].is_ascii_digit() {
+                j += 1;
+                while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                    j += 1;
+                }
+            }
+            if j < chars.len()
+                && (chars[j] == '+' || chars[j] == '-')
+                && (chars[j - 1] == 'e' || chars[j - 1] == 'E')
+            {
+                j += 1;
+                while j < chars.len() && chars[j].is_ascii_digit() {
+                    j += 1;
+                }
+            }
+            // sized based literal: 8'hFF, 4 'b1010
+            let mut k = j;
+            while k < chars.len() && (chars[k] == ' ' || chars[k] == '\t') {
+                k += 1;
+            }
+            if chars.get(k) == Some(&'\'') {
+                if let Some(based_end) = scan_based(&chars, k + 1) {
+                    j = based_end;
+                }
+            }
+            end = j;
+            kind = NUMBER;
+        } else if c == '\'' {
+            if let Some(based_end) = scan_based(&chars, i + 1) {
+                // unsized based literal: 'hFF
+                end = based_end;
+                kind = NUMBER;
+            } else if matches!(chars.get(i + 1), Some('0' | '1' | 'x' | 'X' | 'z' | 'Z'))
+                && !chars.get(i + 2).is_some_and(|c| is_identifier_char(*c))
+            {
+                // unbased unsized literal: '0, '1, 'x, 'z
+                end = i + 2;
+                kind = NUMBER;
+            } else if chars.get(i + 1) == Some(&'{') {
+                // assignment pattern: '{a, b}
+                end = i + 2;
+                kind = OPERATOR_BASE + OPERATORS.iter().position(|op| *op == "'{").unwrap() as Kind;
+            } else {
+                // cast: int'(x)
+                end = i + 1;
+                kind = TICK;
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut j = i;
+            while j < chars.len() && is_identifier_char(chars[j]) {
+                j += 1;
+            }
+            let word: String = chars[i..j].iter().collect();
+            if let Some(index) = KEYWORDS.iter().position(|k| *k == word) {
+                kind = KEYWORD_BASE + index as Kind;
+            } else if let Some(index) = RARE_KEYWORDS.iter().position(|k| *k == word) {
+                kind = RARE_KEYWORD_BASE + index as Kind;
+            } else {
+                kind = 0;
+            }
+            end = j;
+        } else if c == '$' || c == '`' {
+            // system task or function: $display
+            // compiler directive or macro: `define, `WIDTH
+            let mut j = i + 1;
+            while j < chars.len() && is_identifier_char(chars[j]) {
+                j += 1;
+            }
+            end = j;
+            kind = if c == '$' { SYSTEM_NAME } else { DIRECTIVE };
+        } else if c == '\\' {
+            // escaped identifier: \bus[0] , terminated by whitespace
+            let mut j = i + 1;
+            while j < chars.len() && !chars[j].is_whitespace() {
+                j += 1;
+            }
+            end = j;
+            kind = 0;
+        } else if c == '"' {
+            // string literal with backslash escapes
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '"' && chars[j] != '\n' {
+                if chars[j] == '\\' {
+                    j += 1;
+                }
+                j += 1;
+            }
+            end = (j + 1).min(chars.len());
+            kind = STRING;
+        } else if let Some(index) = OPERATORS.iter().position(|op| {
+            op.chars()
+                .enumerate()
+                .all(|(k, ch)| chars.get(i + k) == Some(&ch))
+        }) {
+            end = i + OPERATORS[index].chars().count();
+            kind = OPERATOR_BASE + index as Kind;
+        } else {
+            end = i + 1;
+            kind = UNKNOWN;
+        }
+
+        let start = pos;
+        pos.advance(&chars[i..end]);
+        res.push(start.token(&pos, kind, chars[i..end].iter().collect()));
+        i = end;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize_str;
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "module test (input logic clk); // comment\n  always_ff @(posedge clk) q <= 8'hFF;\nendmodule";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "module");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[1].spelling, "test");
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(tokens[1].column, 8);
+
+        assert_eq!(tokens[2].spelling, "(");
+        assert_eq!(tokens[2].line, 1);
+        assert_eq!(tokens[2].column, 13);
+
+        // comments are dropped
+        assert_eq!(tokens[8].spelling, "always_ff");
+        assert_eq!(tokens[8].line, 2);
+        assert_eq!(tokens[8].column, 3);
+
+        assert_eq!(tokens[16].spelling, "8'hFF");
+        assert_eq!(tokens[16].line, 2);
+        assert_eq!(tokens[16].column, 33);
+
+        assert_eq!(tokens[18].spelling, "endmodule");
+        assert_eq!(tokens[18].line, 3);
+        assert_eq!(tokens[18].column, 1);
+    }
+
+    #[test]
+    fn test_tokenize_keywords() {
+        let code = "package p; typedef struct packed { logic [7:0] a; } s_t; endpackage\ninterface bus_if; modport m (input a); endinterface";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        // keywords are distinct from identifiers and from each other
+        let kinds: Vec<Kind> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(tokens[3].spelling, "typedef");
+        assert_ne!(kinds[0], kinds[1]);
+        assert_ne!(kinds[3], kinds[4]);
+        assert_ne!(kinds[4], kinds[5]);
+        assert_eq!(tokens[18].spelling, "endpackage");
+        assert_eq!(tokens[19].spelling, "interface");
+        assert_ne!(kinds[18], kinds[19]);
+        assert_ne!(kinds[19], kinds[20]);
+    }
+
+    #[test]
+    fn test_tokenize_rare_keywords() {
+        let code = "checker c; bufif0 b(o, i, e); endchecker";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "checker");
+        assert_eq!(tokens[3].spelling, "bufif0");
+        assert_eq!(tokens[13].spelling, "endchecker");
+        assert_ne!(tokens[0].kind, 0);
+        assert_ne!(tokens[0].kind, tokens[3].kind);
+        assert_ne!(tokens[0].kind, tokens[13].kind);
+    }
+}
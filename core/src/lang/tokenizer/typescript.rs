@@ -0,0 +1,289 @@
+use super::{fallback::Syntax, grammar::tokenized, grammar::Grammar, scanner::LineIndex};
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+use std::path::Path;
+use tree_sitter::{Node, Tree, TreeCursor};
+
+/// How type-only syntax (annotations, interfaces, generics, ...) is tokenized
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TypeAnnotations {
+    /// Keep every token of type-only syntax
+    Keep,
+    /// Replace each type-only construct by a single token
+    Normalize,
+    /// Drop type-only syntax, so that TypeScript compares equal to JavaScript
+    #[default]
+    Strip,
+}
+
+#[derive(Default)]
+pub struct TypeScript {
+    pub type_annotations: TypeAnnotations,
+}
+
+impl Tokenize for TypeScript {
+    fn tokenize_content(&self, path: &Path, content: &str) -> Tokenized {
+        let extension = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let tree = match extension.as_str() {
+            "ts" | "mts" | "cts" => {
+                GRAMMAR.parse_as(content, &tree_sitter_typescript::language_typescript())
+            }
+            _ => GRAMMAR.parse(content),
+        };
+        tokenized(content, &GRAMMAR.syntax, tree, |tree| {
+            tokenize_tree(content, tree, self.type_annotations)
+        })
+    }
+
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        tokenized(content, &GRAMMAR.syntax, parse_any(content), |tree| {
+            tokenize_tree(content, tree, self.type_annotations)
+        })
+    }
+}
+
+/// TSX, whose symbol table also gives the kinds of plain TypeScript
+pub static GRAMMAR: Grammar = Grammar::new(
+    "TypeScript",
+    tree_sitter_typescript::language_tsx,
+    &["comment", "html_comment", "hash_bang_line"],
+    &[
+        "identifier",
+        "property_identifier",
+        "private_property_identifier",
+        "shorthand_property_identifier",
+        "shorthand_property_identifier_pattern",
+        "statement_identifier",
+        "type_identifier",
+    ],
+    &[
+        // `number` and `string` are also predefined type keywords, which
+        // are not named
+        &["number"],
+        &["string", "string_fragment", "escape_sequence"],
+        &["regex"],
+        &["jsx_text", "html_character_reference"],
+    ],
+    Syntax {
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        quotes: &['"', '\'', '`'],
+    },
+);
+
+// kind for type-only syntax in `TypeAnnotations::Normalize` mode, past the
+// grammar's
+fn type_kind() -> Kind {
+    GRAMMAR.kind_count() as Kind
+}
+
+// subtrees that only exist for the type checker
+fn is_type_only(node: &Node) -> bool {
+    matches!(
+        node.kind(),
+        "type_annotation"
+            | "type_arguments"
+            | "type_parameters"
+            | "interface_declaration"
+            | "type_alias_declaration"
+            | "ambient_declaration"
+            | "abstract_method_signature"
+            | "index_signature"
+            | "implements_clause"
+            | "accessibility_modifier"
+            | "override_modifier"
+            | "type_predicate_annotation"
+            | "asserts_annotation"
+            | "omitting_type_annotation"
+            | "opting_type_annotation"
+            | "adding_type_annotation"
+            | "readonly"
+    )
+}
+
+// single tokens that only exist for the type checker
+fn is_type_only_token(node: &Node) -> bool {
+    let parent = node.parent().map(|p| p.kind()).unwrap_or_default();
+    match node.kind() {
+        // non-null assertion: `x!`
+        "!" => parent == "non_null_expression" || parent == "public_field_definition",
+        // optional parameter or property: `x?: T`
+        "?" => parent == "optional_parameter" || parent == "public_field_definition",
+        _ => false,
+    }
+}
+
+fn push(res: &mut Vec<Token>, lines: &LineIndex, node: &Node, end_byte: usize, kind: Kind) {
+    let bytes = node.start_byte()..end_byte;
+    res.push(lines.token(kind, lines.content[bytes.clone()].to_string(), bytes));
+}
+
+// move to the last sibling, skipping all siblings in between
+fn skip_siblings(cursor: &mut TreeCursor) {
+    while cursor.goto_next_sibling() {}
+}
+
+// the TypeScript grammar, falling back to TSX if it does not parse
+fn parse_any(content: &str) -> anyhow::Result<Tree> {
+    let tree = GRAMMAR.parse_as(content, &tree_sitter_typescript::language_typescript())?;
+    if tree.root_node().has_error() {
+        return GRAMMAR.parse(content);
+    }
+    Ok(tree)
+}
+
+/// Tokenize with the TypeScript grammar, falling back to TSX if it does not parse
+pub fn tokenize_str(
+    content: &str,
+    type_annotations: TypeAnnotations,
+) -> anyhow::Result<Vec<Token>> {
+    Ok(tokenize_tree(
+        content,
+        &parse_any(content)?,
+        type_annotations,
+    ))
+}
+
+fn tokenize_tree(content: &str, tree: &Tree, type_annotations: TypeAnnotations) -> Vec<Token> {
+    let mut res = vec![];
+    let lines = LineIndex::new(content);
+    let root_node = tree.root_node();
+    let mut cursor = root_node.walk();
+    loop {
+        let node = cursor.node();
+        let mut descend = true;
+
+        'output: {
+            if type_annotations != TypeAnnotations::Keep {
+                let parent = node.parent().map(|p| p.kind()).unwrap_or_default();
+                if (node.kind() == "as" && parent == "as_expression")
+                    || (node.kind() == "satisfies" && parent == "satisfies_expression")
+                {
+                    // `x as T`: drop the keyword and the type after it
+                    if type_annotations == TypeAnnotations::Normalize {
+                        let end_byte = node.parent().unwrap().end_byte();
+                        push(&mut res, &lines, &node, end_byte, type_kind());
+                    }
+                    skip_siblings(&mut cursor);
+                    descend = false;
+                    break 'output;
+                }
+                if is_type_only(&node) || is_type_only_token(&node) {
+                    if type_annotations == TypeAnnotations::Normalize {
+                        push(&mut res, &lines, &node, node.end_byte(), type_kind());
+                    }
+                    descend = false;
+                    break 'output;
+                }
+            }
+
+            // strings and regular expressions are treated as a single token
+            if GRAMMAR.is_single(&node) {
+                descend = false;
+            } else if node.child_count() > 0 {
+                break 'output;
+            }
+            if let Some(kind) = GRAMMAR.kind(&node) {
+                push(&mut res, &lines, &node, node.end_byte(), kind);
+            }
+        }
+
+        if descend && cursor.goto_first_child() {
+            continue;
+        }
+
+        if cursor.goto_next_sibling() {
+            continue;
+        }
+
+        while cursor.goto_parent() && !cursor.goto_next_sibling() {}
+
+        if cursor.node() == tree.root_node() {
+            break;
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize_str, TypeAnnotations};
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "function add(a: number, b: number): number {\n  // sum\n  return a + b;\n}";
+        let tokens = tokenize_str(code, TypeAnnotations::Keep).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "function");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[1].spelling, "add");
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(tokens[1].column, 10);
+
+        assert_eq!(tokens[4].spelling, ":");
+        assert_eq!(tokens[4].line, 1);
+        assert_eq!(tokens[4].column, 15);
+
+        assert_eq!(tokens[5].spelling, "number");
+        assert_eq!(tokens[5].line, 1);
+        assert_eq!(tokens[5].column, 17);
+
+        // comment is skipped
+        assert_eq!(tokens[14].spelling, "return");
+        assert_eq!(tokens[14].line, 3);
+        assert_eq!(tokens[14].column, 3);
+    }
+
+    #[test]
+    fn test_strip_types() {
+        let js = "function add(a, b) {\n  return a + b;\n}\nconst x = add(1, 2);";
+        let ts = "interface Pair { a: number }\nfunction add<T>(a: number, b?: number): number {\n  return a + b!;\n}\nconst x: number = add<number>(1, 2) as number;";
+        let js_tokens = tokenize_str(js, TypeAnnotations::Strip).unwrap();
+        let ts_tokens = tokenize_str(ts, TypeAnnotations::Strip).unwrap();
+
+        eprintln!("{:?}", ts_tokens);
+
+        let js_kind: Vec<Kind> = js_tokens.iter().map(|t| t.kind).collect();
+        let ts_kind: Vec<Kind> = ts_tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(js_kind, ts_kind);
+    }
+
+    #[test]
+    fn test_normalize_types() {
+        let left = "let x: number = y as number;";
+        let right = "let x: Array<string> = y as string[];";
+        let left_tokens = tokenize_str(left, TypeAnnotations::Normalize).unwrap();
+        let right_tokens = tokenize_str(right, TypeAnnotations::Normalize).unwrap();
+
+        eprintln!("{:?}", left_tokens);
+
+        assert_eq!(left_tokens[2].spelling, ": number");
+        assert_eq!(left_tokens[5].spelling, "as number");
+
+        let left_kind: Vec<Kind> = left_tokens.iter().map(|t| t.kind).collect();
+        let right_kind: Vec<Kind> = right_tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(left_kind, right_kind);
+    }
+
+    #[test]
+    fn test_tsx() {
+        let code = "const App = () => <div className=\"app\">Hello {name}</div>;";
+        let tokens = tokenize_str(code, TypeAnnotations::Strip).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[6].spelling, "<");
+        assert_eq!(tokens[7].spelling, "div");
+        assert_eq!(tokens[11].spelling, ">");
+        assert_eq!(tokens[12].spelling, "Hello ");
+    }
+}
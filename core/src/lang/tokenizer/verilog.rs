@@ -1,25 +1,34 @@
+use super::fallback::{recover, Syntax};
+use super::scanner::LineIndex;
 use crate::lang::Tokenize;
-use crate::token::Token;
+use crate::token::{Kind, Token, Tokenized};
 use verilog_lang::lexer::Lexer;
 
 pub struct Verilog;
 
 impl Tokenize for Verilog {
-    fn tokenize_str(&self, content: &str) -> anyhow::Result<Vec<Token>> {
-        tokenize_str(content)
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, tokenize_str)
     }
 }
 
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"'],
+};
+
 pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
     let lexer = Lexer::lex(content);
+    let lines = LineIndex::new(content);
     let mut res = vec![];
     for token in lexer.tokens {
-        res.push(Token {
-            kind: token.token as u8,
-            spelling: token.text.to_string(),
-            line: token.span.from.row as u32 + 1,
-            column: token.span.from.col as u32 + 1,
-        });
+        let start = lines.offset(token.text);
+        res.push(lines.token(
+            token.token as Kind,
+            token.text.to_string(),
+            start..start + token.text.len(),
+        ));
     }
     Ok(res)
 }
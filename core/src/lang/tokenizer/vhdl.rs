@@ -0,0 +1,393 @@
+use super::{
+    fallback::{recover, Syntax},
+    scanner::Position,
+};
+use crate::lang::Tokenize;
+use crate::token::{Kind, Token, Tokenized};
+
+pub struct Vhdl;
+
+impl Tokenize for Vhdl {
+    fn tokenize_str(&self, content: &str) -> Tokenized {
+        recover(content, &SYNTAX, tokenize_str)
+    }
+}
+
+const SYNTAX: Syntax = Syntax {
+    line_comments: &["--"],
+    block_comments: &[("/*", "*/")],
+    quotes: &['"'],
+};
+
+// IEEE 1076-2008 reserved words
+const KEYWORDS: [&str; 115] = [
+    "abs",
+    "access",
+    "after",
+    "alias",
+    "all",
+    "and",
+    "architecture",
+    "array",
+    "assert",
+    "assume",
+    "assume_guarantee",
+    "attribute",
+    "begin",
+    "block",
+    "body",
+    "buffer",
+    "bus",
+    "case",
+    "component",
+    "configuration",
+    "constant",
+    "context",
+    "cover",
+    "default",
+    "disconnect",
+    "downto",
+    "else",
+    "elsif",
+    "end",
+    "entity",
+    "exit",
+    "fairness",
+    "file",
+    "for",
+    "force",
+    "function",
+    "generate",
+    "generic",
+    "group",
+    "guarded",
+    "if",
+    "impure",
+    "in",
+    "inertial",
+    "inout",
+    "is",
+    "label",
+    "library",
+    "linkage",
+    "literal",
+    "loop",
+    "map",
+    "mod",
+    "nand",
+    "new",
+    "next",
+    "nor",
+    "not",
+    "null",
+    "of",
+    "on",
+    "open",
+    "or",
+    "others",
+    "out",
+    "package",
+    "parameter",
+    "port",
+    "postponed",
+    "procedure",
+    "process",
+    "property",
+    "protected",
+    "pure",
+    "range",
+    "record",
+    "register",
+    "reject",
+    "release",
+    "rem",
+    "report",
+    "restrict",
+    "restrict_guarantee",
+    "return",
+    "rol",
+    "ror",
+    "select",
+    "sequence",
+    "severity",
+    "shared",
+    "signal",
+    "sla",
+    "sll",
+    "sra",
+    "srl",
+    "strong",
+    "subtype",
+    "then",
+    "to",
+    "transport",
+    "type",
+    "unaffected",
+    "units",
+    "until",
+    "use",
+    "variable",
+    "vmode",
+    "vprop",
+    "vunit",
+    "wait",
+    "when",
+    "while",
+    "with",
+    "xnor",
+    "xor",
+];
+
+// longer operators first, so that the longest one matches
+const OPERATORS: [&str; 39] = [
+    "?/=", "?<=", "?>=", "**", ":=", "/=", ">=", "<=", "=>", "<>", "<<", ">>", "??", "?=", "?<",
+    "?>", "&", "'", "(", ")", "*", "+", ",", "-", ".", "/", ":", ";", "<", "=", ">", "`", "|", "[",
+    "]", "?", "@", "^", "!",
+];
+
+// kind: 0 for identifiers, [1, 4] for literals
+const NUMBER: Kind = 1;
+const CHARACTER: Kind = 2;
+const STRING: Kind = 3;
+const BIT_STRING: Kind = 4;
+// kind: [5, 5+KEYWORDS.len()) for keywords, then operators
+const KEYWORD_BASE: Kind = 5;
+const OPERATOR_BASE: Kind = KEYWORD_BASE + KEYWORDS.len() as Kind;
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub fn tokenize_str(content: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut res = vec![];
+    let mut i = 0;
+    let mut pos = Position::new();
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // whitespace
+        if c.is_whitespace() {
+            pos.advance(&chars[i..i + 1]);
+            i += 1;
+            continue;
+        }
+
+        // comments
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            let mut end = i;
+            while end < chars.len() && chars[end] != '\n' {
+                end += 1;
+            }
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let mut end = i + 2;
+            while end + 1 < chars.len() && !(chars[end] == '*' && chars[end + 1] == '/') {
+                end += 1;
+            }
+            let end = (end + 2).min(chars.len());
+            pos.advance(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        let end;
+        let kind;
+        if c.is_ascii_digit() {
+            // decimal literal: 1_000.5e-3, or based literal: 16#FF_FF#
+            let mut j = i;
+            while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                j += 1;
+            }
+            if j < chars.len() && (chars[j] == '#' || chars[j] == ':') {
+                // based literal
+                let delimiter = chars[j];
+                j += 1;
+                while j < chars.len() && chars[j] != delimiter && chars[j] != '\n' {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j] == delimiter {
+                    j += 1;
+                }
+                // optional exponent
+                if j < chars.len() && (chars[j] == 'e' || chars[j] == 'E') {
+                    j += 1;
+                    while j < chars.len()
+                        && (chars[j].is_ascii_digit() || chars[j] == '+' || chars[j] == '-')
+                    {
+                        j += 1;
+                    }
+                }
+                kind = NUMBER;
+            } else if j < chars.len() && chars[j] == '"' {
+                // bit string literal with size: 8X"FF"
+                j += 1;
+                while j < chars.len() && chars[j] != '"' && chars[j] != '\n' {
+                    j += 1;
+                }
+                j = (j + 1).min(chars.len());
+                kind = BIT_STRING;
+            } else {
+                if j + 1 < chars.len() && chars[j] == '.' && chars[j + 1].is_ascii_digit() {
+                    j += 1;
+                    while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                        j += 1;
+                    }
+                }
+                // exponent sign: 1.0e-3
+                if j < chars.len()
+                    && (chars[j] == '+' || chars[j] == '-')
+                    && (chars[j - 1] == 'e' || chars[j - 1] == 'E')
+                {
+                    j += 1;
+                    while j < chars.len() && chars[j].is_ascii_digit() {
+                        j += 1;
+                    }
+                }
+                kind = NUMBER;
+            }
+            end = j;
+        } else if c.is_alphabetic() {
+            let mut j = i;
+            while j < chars.len() && is_identifier_char(chars[j]) {
+                j += 1;
+            }
+            let word: String = chars[i..j].iter().collect::<String>().to_ascii_lowercase();
+            if j < chars.len()
+                && chars[j] == '"'
+                && matches!(
+                    word.as_str(),
+                    "b" | "o" | "x" | "d" | "ub" | "uo" | "ux" | "sb" | "so" | "sx"
+                )
+            {
+                // bit string literal: X"FF", B"0101", UX"F0"
+                j += 1;
+                while j < chars.len() && chars[j] != '"' && chars[j] != '\n' {
+                    j += 1;
+                }
+                j = (j + 1).min(chars.len());
+                kind = BIT_STRING;
+            } else if let Some(index) = KEYWORDS.iter().position(|k| *k == word) {
+                kind = KEYWORD_BASE + index as Kind;
+            } else {
+                kind = 0;
+            }
+            end = j;
+        } else if c == '\\' {
+            // extended identifier: \foo bar\
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '\\' && chars[j] != '\n' {
+                j += 1;
+            }
+            end = (j + 1).min(chars.len());
+            kind = 0;
+        } else if c == '"' {
+            // string literal, with "" as escaped quote
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '\n' {
+                if chars[j] == '"' {
+                    if chars.get(j + 1) == Some(&'"') {
+                        j += 2;
+                        continue;
+                    }
+                    break;
+                }
+                j += 1;
+            }
+            end = (j + 1).min(chars.len());
+            kind = STRING;
+        } else if c == '\''
+            && chars.get(i + 2) == Some(&'\'')
+            && !res.last().is_some_and(|t: &Token| {
+                // a tick after a name or closing bracket is an attribute: clk'event
+                t.kind == 0
+                    || t.spelling == ")"
+                    || t.spelling == "]"
+                    || t.spelling.eq_ignore_ascii_case("all")
+            })
+        {
+            // character literal: 'a'
+            end = i + 3;
+            kind = CHARACTER;
+        } else if let Some(index) = OPERATORS.iter().position(|op| {
+            op.chars()
+                .enumerate()
+                .all(|(k, ch)| chars.get(i + k) == Some(&ch))
+        }) {
+            end = i + OPERATORS[index].chars().count();
+            kind = OPERATOR_BASE + index as Kind;
+        } else {
+            // unknown character
+            end = i + 1;
+            kind = OPERATOR_BASE + OPERATORS.len() as Kind;
+        }
+
+        let start = pos;
+        pos.advance(&chars[i..end]);
+        res.push(start.token(&pos, kind, chars[i..end].iter().collect()));
+        i = end;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize_str;
+    use crate::token::Kind;
+
+    #[test]
+    fn test_tokenize() {
+        let code = "entity test is -- comment\n  port (clk : in std_logic);\nend entity;";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[0].spelling, "entity");
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(tokens[0].column, 1);
+
+        assert_eq!(tokens[1].spelling, "test");
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(tokens[1].column, 8);
+
+        assert_eq!(tokens[3].spelling, "port");
+        assert_eq!(tokens[3].line, 2);
+        assert_eq!(tokens[3].column, 3);
+
+        assert_eq!(tokens[4].spelling, "(");
+        assert_eq!(tokens[4].line, 2);
+        assert_eq!(tokens[4].column, 8);
+
+        assert_eq!(tokens[11].spelling, "end");
+        assert_eq!(tokens[11].line, 3);
+        assert_eq!(tokens[11].column, 1);
+    }
+
+    #[test]
+    fn test_tokenize_literals() {
+        let code = "if clk'event and clk = '1' then q <= X\"FF\" & 16#0F# & 1.5e-3; end if;";
+        let tokens = tokenize_str(code).unwrap();
+
+        eprintln!("{:?}", tokens);
+
+        assert_eq!(tokens[2].spelling, "'");
+        assert_eq!(tokens[3].spelling, "event");
+        assert_eq!(tokens[7].spelling, "'1'");
+        assert_eq!(tokens[11].spelling, "X\"FF\"");
+        assert_eq!(tokens[13].spelling, "16#0F#");
+        assert_eq!(tokens[15].spelling, "1.5e-3");
+    }
+
+    #[test]
+    fn test_tokenize_case_insensitive() {
+        let lower = tokenize_str("process (clk) begin\nend process;").unwrap();
+        let upper = tokenize_str("PROCESS (CLK) Begin\nEND Process;").unwrap();
+        let lower_kind: Vec<Kind> = lower.iter().map(|t| t.kind).collect();
+        let upper_kind: Vec<Kind> = upper.iter().map(|t| t.kind).collect();
+        assert_eq!(lower_kind, upper_kind);
+    }
+}
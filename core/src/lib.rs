@@ -1,4 +1,6 @@
 pub mod common;
+pub mod decode;
 pub mod lang;
 pub mod matching;
+pub mod rkr_gst;
 pub mod token;
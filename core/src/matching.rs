@@ -1,18 +1,16 @@
 use crate::lang::Language;
-use crate::token::Token;
+use crate::rkr_gst::{self, Match};
+use crate::token::{Kind, Token};
 
-use bitvec::bitvec;
 use log::*;
-use rkr_gst::Match;
+use std::ops::Range;
 
 pub fn compute_matches_from_token(
     token_left: &[Token],
-    token_kind_left: &[u8],
-    lines_left: &[&str],
+    token_kind_left: &[Kind],
     token_right: &[Token],
-    token_kind_right: &[u8],
-    lines_right: &[&str],
-    template_kind: Option<&[u8]>,
+    token_kind_right: &[Kind],
+    template_kind: Option<&[Kind]>,
     initial_search_length: Option<usize>,
     minimum_match_length: Option<usize>,
 ) -> Vec<Match> {
@@ -92,33 +90,28 @@ pub fn compute_matches_from_token(
         filter(&right_template_matches, false);
     }
 
-    // ensure matches are on distinct lines
-    let mut bitvec_left = bitvec![0; lines_left.len()];
-    let mut bitvec_right = bitvec![0; lines_right.len()];
-    let mut i = 0;
-    while i < matches.len() {
-        let m = matches[i];
-        let line_from_left = token_left[m.pattern_index].line as usize - 1;
-        let line_to_left = token_left[m.pattern_index + m.length - 1].line as usize - 1;
-        let line_from_right = token_right[m.text_index].line as usize - 1;
-        let line_to_right = token_right[m.text_index + m.length - 1].line as usize - 1;
-        if !bitvec_left[line_from_left]
-            && !bitvec_left[line_to_left]
-            && !bitvec_right[line_from_right]
-            && !bitvec_right[line_to_right]
-        {
-            // safe
-            for i in line_from_left..=line_to_left {
-                bitvec_left.set(i, true);
-            }
-            for i in line_from_right..=line_to_right {
-                bitvec_right.set(i, true);
-            }
-            i += 1;
-        } else {
-            matches.remove(i);
+    // ensure matches cover distinct characters: tiles never share tokens, but
+    // tokens of a macro expansion share the span of the invocation
+    let span = |tokens: &[Token], from: usize, length: usize| {
+        tokens[from].bytes.start..tokens[from + length - 1].bytes.end
+    };
+    let overlaps = |taken: &[Range<usize>], range: &Range<usize>| {
+        taken
+            .iter()
+            .any(|t| t.start < range.end && range.start < t.end)
+    };
+    let mut taken_left = vec![];
+    let mut taken_right = vec![];
+    matches.retain(|m| {
+        let left = span(token_left, m.pattern_index, m.length);
+        let right = span(token_right, m.text_index, m.length);
+        if overlaps(&taken_left, &left) || overlaps(&taken_right, &right) {
+            return false;
         }
-    }
+        taken_left.push(left);
+        taken_right.push(right);
+        true
+    });
 
     matches
 }
@@ -129,6 +122,40 @@ pub struct Block {
     pub left_line_to: usize,
     pub right_line_from: usize,
     pub right_line_to: usize,
+    // 0-based, in characters, on the first and the last line; `_to` is
+    // exclusive
+    pub left_column_from: usize,
+    pub left_column_to: usize,
+    pub right_column_from: usize,
+    pub right_column_to: usize,
+}
+
+/// 0-based line and character column where `tokens` start and end
+pub fn token_range(tokens: &[Token]) -> ((usize, usize), (usize, usize)) {
+    let (first, last) = (&tokens[0], &tokens[tokens.len() - 1]);
+    (
+        (first.line as usize - 1, first.column as usize - 1),
+        (last.end_line as usize - 1, last.end_column as usize - 1),
+    )
+}
+
+/// Text between two 0-based (line, character column) positions of `lines`,
+/// with newlines between the lines; `to` may be past the last line
+pub fn slice_lines<S: AsRef<str>>(lines: &[S], from: (usize, usize), to: (usize, usize)) -> String {
+    let mut res = String::new();
+    for line in from.0..=to.0 {
+        let Some(text) = lines.get(line) else {
+            break;
+        };
+        let skip = if line == from.0 { from.1 } else { 0 };
+        if line == to.0 {
+            res.extend(text.as_ref().chars().take(to.1).skip(skip));
+        } else {
+            res.extend(text.as_ref().chars().skip(skip));
+            res.push('\n');
+        }
+    }
+    res
 }
 
 /// Compute matching blocks via RKR-GST algorithm
@@ -140,26 +167,26 @@ pub fn compute_matching_blocks_from_text(
     initial_search_length: Option<usize>,
     minimum_match_length: Option<usize>,
 ) -> anyhow::Result<Vec<Block>> {
-    let token_left = crate::lang::tokenize_str(left, language)?;
-    let token_kind_left: Vec<u8> = token_left.iter().map(|t| t.kind).collect();
-    let lines_left: Vec<&str> = left.lines().collect();
+    // malformed code is compared as far as it could be tokenized
+    let token_left = crate::lang::tokenize_str(left, language)?.tokens;
+    let token_kind_left: Vec<Kind> = token_left.iter().map(|t| t.kind).collect();
 
-    let token_right = crate::lang::tokenize_str(right, language).unwrap();
-    let token_kind_right: Vec<u8> = token_right.iter().map(|t| t.kind).collect();
-    let lines_right: Vec<&str> = right.lines().collect();
+    let token_right = crate::lang::tokenize_str(right, language)?.tokens;
+    let token_kind_right: Vec<Kind> = token_right.iter().map(|t| t.kind).collect();
 
-    let template_kind: Option<Vec<u8>> = template.as_ref().map(|t| {
-        let token_template = crate::lang::tokenize_str(t, language).unwrap();
-        token_template.iter().map(|t| t.kind).collect()
-    });
+    let template_kind: Option<Vec<Kind>> = match template {
+        Some(t) => {
+            let token_template = crate::lang::tokenize_str(t, language)?.tokens;
+            Some(token_template.iter().map(|t| t.kind).collect())
+        }
+        None => None,
+    };
 
     let matches = compute_matches_from_token(
         &token_left,
         &token_kind_left,
-        &lines_left,
         &token_right,
         &token_kind_right,
-        &lines_right,
         template_kind.as_deref(),
         initial_search_length,
         minimum_match_length,
@@ -168,21 +195,108 @@ pub fn compute_matching_blocks_from_text(
     let mut res = vec![];
 
     for (idx, m) in matches.iter().enumerate() {
-        let line_from_left = token_left[m.pattern_index].line as usize - 1;
-        let line_to_left = token_left[m.pattern_index + m.length - 1].line as usize - 1;
-        let line_from_right = token_right[m.text_index].line as usize - 1;
-        let line_to_right = token_right[m.text_index + m.length - 1].line as usize - 1;
+        let (left_from, left_to) = token_range(&token_left[m.pattern_index..][..m.length]);
+        let (right_from, right_to) = token_range(&token_right[m.text_index..][..m.length]);
 
         res.push(Block {
-            left_line_from: line_from_left,
-            left_line_to: line_to_left,
-            right_line_from: line_from_right,
-            right_line_to: line_to_right,
+            left_line_from: left_from.0,
+            left_line_to: left_to.0,
+            right_line_from: right_from.0,
+            right_line_to: right_to.0,
+            left_column_from: left_from.1,
+            left_column_to: left_to.1,
+            right_column_from: right_from.1,
+            right_column_to: right_to.1,
         });
 
         debug!("Match #{}:", idx + 1);
-        debug!("Left L{}-L{}", line_from_left, line_to_left);
-        debug!("Right L{}-L{}", line_from_right, line_to_right);
+        debug!(
+            "Left L{}C{}-L{}C{}",
+            left_from.0, left_from.1, left_to.0, left_to.1
+        );
+        debug!(
+            "Right L{}C{}-L{}C{}",
+            right_from.0, right_from.1, right_to.0, right_to.1
+        );
     }
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_matching_blocks_from_text, slice_lines};
+    use crate::lang::Language;
+
+    #[test]
+    fn test_slice_lines() {
+        let lines = ["let a = 1;", "let ü = 2;", "end"];
+        assert_eq!(slice_lines(&lines, (0, 4), (0, 5)), "a");
+        assert_eq!(slice_lines(&lines, (0, 8), (1, 5)), "1;\nlet ü");
+        assert_eq!(slice_lines(&lines, (1, 5), (3, 0)), " = 2;\nend\n");
+    }
+
+    #[test]
+    fn test_columns() {
+        let left = "let a = f(1, 2, 3, 4);\n";
+        let right = "g();\n\ng(); b = f(1, 2, 3, 4); g();\n";
+        let blocks = compute_matching_blocks_from_text(
+            left,
+            right,
+            Language::JavaScript,
+            &None,
+            Some(4),
+            Some(4),
+        )
+        .unwrap();
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks[0];
+        assert_eq!((block.left_line_from, block.left_column_from), (0, 4));
+        assert_eq!((block.left_line_to, block.left_column_to), (0, 22));
+        assert_eq!((block.right_line_from, block.right_column_from), (2, 5));
+        assert_eq!((block.right_line_to, block.right_column_to), (2, 23));
+
+        let right_lines: Vec<&str> = right.lines().collect();
+        assert_eq!(
+            slice_lines(
+                &right_lines,
+                (block.right_line_from, block.right_column_from),
+                (block.right_line_to, block.right_column_to)
+            ),
+            "b = f(1, 2, 3, 4);"
+        );
+    }
+
+    #[test]
+    fn test_same_line() {
+        // two tiles on one line, in a different order
+        let left = "f(1, 2, 3); while (x) { y(); }\n";
+        let right = "while (x) { y(); } + f(1, 2, 3);\n";
+        let mut blocks = compute_matching_blocks_from_text(
+            left,
+            right,
+            Language::JavaScript,
+            &None,
+            Some(4),
+            Some(4),
+        )
+        .unwrap();
+        blocks.sort_by_key(|b| b.left_column_from);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            (blocks[0].left_column_from, blocks[0].left_column_to),
+            (0, 11)
+        );
+        assert_eq!(
+            (blocks[0].right_column_from, blocks[0].right_column_to),
+            (21, 32)
+        );
+        assert_eq!(
+            (blocks[1].left_column_from, blocks[1].left_column_to),
+            (12, 30)
+        );
+        assert_eq!(
+            (blocks[1].right_column_from, blocks[1].right_column_to),
+            (0, 18)
+        );
+    }
+}
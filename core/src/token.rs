@@ -1,8 +1,56 @@
+use crate::decode::Encoding;
+use std::{fmt::Display, ops::Range};
+
+/// Kind of a token, compared instead of its spelling. Two bytes are plenty
+/// for any grammar's distinct kinds, without the memory of wider ones.
+pub type Kind = u16;
+
 #[derive(Clone, Debug)]
 pub struct Token {
-    pub kind: u8,
+    pub kind: Kind,
     pub spelling: String,
+    // 1-based, in characters
+    pub line: u32,
+    pub column: u32,
+    // just past the token
+    pub end_line: u32,
+    pub end_column: u32,
+    /// in the tokenized content
+    pub bytes: Range<usize>,
+}
+
+/// Part of the code that could not be tokenized properly
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
     // 1-based
     pub line: u32,
     pub column: u32,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "L{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+// lexers return it as an error to tell where they failed
+impl std::error::Error for Diagnostic {}
+
+/// Best-effort tokens, with diagnostics if the code was only partly parsed
+#[derive(Clone, Debug, Default)]
+pub struct Tokenized {
+    pub tokens: Vec<Token>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// the file was decoded from; token positions are in the decoded content
+    pub encoding: Encoding,
+}
+
+impl From<Vec<Token>> for Tokenized {
+    fn from(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            ..Default::default()
+        }
+    }
 }
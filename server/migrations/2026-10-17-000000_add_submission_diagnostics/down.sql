@@ -0,0 +1 @@
+ALTER TABLE submissions DROP COLUMN diagnostics;
\ No newline at end of file
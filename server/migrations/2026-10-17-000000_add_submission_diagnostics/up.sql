@@ -0,0 +1,2 @@
+-- one per line, empty if the code was fully parsed
+ALTER TABLE submissions ADD COLUMN diagnostics TEXT NOT NULL DEFAULT '';
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- 0-based, in characters, on the first and the last line; NULL for blocks
+-- of whole lines
+ALTER TABLE blocks ADD COLUMN left_column_from INT;
+ALTER TABLE blocks ADD COLUMN left_column_to INT;
+ALTER TABLE blocks ADD COLUMN right_column_from INT;
+ALTER TABLE blocks ADD COLUMN right_column_to INT;
@@ -6,6 +6,7 @@ pub mod db;
 pub mod models;
 pub mod render;
 pub mod schema;
+pub mod serve;
 pub mod session;
 pub mod submit;
 pub mod work;
@@ -61,6 +61,11 @@ pub struct NewBlock {
     pub left_line_to: i32,
     pub right_line_from: i32,
     pub right_line_to: i32,
+    // 0-based, in characters; none for whole lines
+    pub left_column_from: Option<i32>,
+    pub left_column_to: Option<i32>,
+    pub right_column_from: Option<i32>,
+    pub right_column_to: Option<i32>,
 }
 
 #[derive(Debug, Queryable)]
@@ -72,6 +77,11 @@ pub struct Block {
     pub left_line_to: i32,
     pub right_line_from: i32,
     pub right_line_to: i32,
+    // 0-based, in characters; none for whole lines
+    pub left_column_from: Option<i32>,
+    pub left_column_to: Option<i32>,
+    pub right_column_from: Option<i32>,
+    pub right_column_to: Option<i32>,
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -80,6 +90,8 @@ pub struct NewSubmission {
     pub job_id: i32,
     pub name: String,
     pub code: String,
+    // one per line
+    pub diagnostics: String,
 }
 
 #[derive(Debug, Queryable)]
@@ -88,4 +100,6 @@ pub struct Submission {
     pub job_id: i32,
     pub name: String,
     pub code: String,
+    // one per line
+    pub diagnostics: String,
 }
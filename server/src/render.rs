@@ -4,9 +4,52 @@ use crate::{
     models::{Block, Job, Match, Submission},
 };
 use actix_web::{get, http::header, web, HttpResponse, Result};
-use core::common::gen_svg;
+use core::{common::gen_svg, lang::notebook::describe_lines, matching::slice_lines};
 use diesel::prelude::*;
 
+// submissions that were only partly parsed are marked
+fn display_name(s: &Submission) -> String {
+    if s.diagnostics.is_empty() {
+        s.name.clone()
+    } else {
+        format!("{} (partly parsed)", s.name)
+    }
+}
+
+// 0-based line range, or cells and lines within the notebooks flattened into
+// a submission
+fn describe_range(code: &str, from: i32, to: i32) -> String {
+    describe_lines(code, from as u32 + 1, to as u32 + 1)
+        .unwrap_or_else(|| format!("{}-{}", from, to))
+}
+
+// 0-based line and character column where a block starts and ends on one
+// side; blocks stored without columns span whole lines
+fn block_range(b: &Block, is_left: bool) -> ((usize, usize), (usize, usize)) {
+    let (line_from, line_to, column_from, column_to) = if is_left {
+        (
+            b.left_line_from,
+            b.left_line_to,
+            b.left_column_from,
+            b.left_column_to,
+        )
+    } else {
+        (
+            b.right_line_from,
+            b.right_line_to,
+            b.right_column_from,
+            b.right_column_to,
+        )
+    };
+    match (column_from, column_to) {
+        (Some(from), Some(to)) => (
+            (line_from as usize, from as usize),
+            (line_to as usize, to as usize),
+        ),
+        _ => ((line_from as usize, 0), (line_to as usize + 1, 0)),
+    }
+}
+
 #[get("/results/{slug}/{match_id}/{frame}")]
 pub async fn render_match_frame(
     pool: web::Data<DbPool>,
@@ -49,13 +92,21 @@ pub async fn render_match_frame(
             .filter(crate::schema::submissions::dsl::id.eq(m.left_submission_id))
             .first::<Submission>(&mut conn)
             .map_err(err)?;
-        res += &format!("<th>{} ({}%)</th>", left_s.name, m.left_match_rate);
+        res += &format!(
+            "<th>{} ({}%)</th>",
+            display_name(&left_s),
+            m.left_match_rate
+        );
         res += &format!("<th>{}</th>", gen_svg("#FF0000", m.left_match_rate));
         let right_s = crate::schema::submissions::dsl::submissions
             .filter(crate::schema::submissions::dsl::id.eq(m.right_submission_id))
             .first::<Submission>(&mut conn)
             .map_err(err)?;
-        res += &format!("<th>{} ({}%)</th>", right_s.name, m.right_match_rate);
+        res += &format!(
+            "<th>{} ({}%)</th>",
+            display_name(&right_s),
+            m.right_match_rate
+        );
         res += &format!("<th>{}</th>", gen_svg("#FF0000", m.right_match_rate));
         res += "<th> </th>";
         res += "</tr>";
@@ -76,15 +127,17 @@ pub async fn render_match_frame(
         for (idx, block) in blocks.iter() {
             res += "<tr>";
             res += &format!(
-                "<td><a href=\"./left.html#{}\" target=\"left\">{}-{}</td>",
-                block.left_line_from, block.left_line_from, block.left_line_to
+                "<td><a href=\"./left.html#{}\" target=\"left\">{}</td>",
+                block.left_line_from,
+                describe_range(&left_s.code, block.left_line_from, block.left_line_to)
             );
             let left_ratio =
                 (block.left_line_to - block.left_line_from + 1) * 100 / left_lines as i32;
             res += &format!("<td>{}</td>", gen_svg(colors[idx % 5], left_ratio));
             res += &format!(
-                "<td><a href=\"./right.html#{}\" target=\"right\">{}-{}</td>",
-                block.right_line_from, block.right_line_from, block.right_line_to
+                "<td><a href=\"./right.html#{}\" target=\"right\">{}</td>",
+                block.right_line_from,
+                describe_range(&right_s.code, block.right_line_from, block.right_line_to)
             );
             let right_ratio =
                 (block.right_line_to - block.right_line_from + 1) * 100 / right_lines as i32;
@@ -106,28 +159,27 @@ pub async fn render_match_frame(
             .map_err(err)?;
         let lines: Vec<&str> = s.code.lines().collect();
 
-        res = "<html><head><meta charset=\"UTF-8\"></head><body><pre>".to_string();
-        let mut last_line = 0;
+        res = "<html><head><meta charset=\"UTF-8\"></head><body>".to_string();
+        if !s.diagnostics.is_empty() {
+            res += "<p>Only partly parsed:</p><ul>";
+            for diagnostic in s.diagnostics.lines() {
+                res += &format!("<li>{}</li>", html_escape::encode_text(diagnostic));
+            }
+            res += "</ul>";
+        }
+        res += "<pre>";
+        let mut last = (0, 0);
 
         // add index to blocks before sorting
         // so that index remains sync-ed in left & right panels
         let mut blocks: Vec<(usize, Block)> = blocks.into_iter().enumerate().collect();
 
-        // sort by line_from
-        blocks.sort_by_key(|b| {
-            if is_left {
-                b.1.left_line_from
-            } else {
-                b.1.right_line_from
-            }
-        });
+        // sort by where they start
+        blocks.sort_by_key(|b| block_range(&b.1, is_left).0);
 
         for (idx, b) in blocks.iter() {
-            let line_from = if is_left {
-                b.left_line_from
-            } else {
-                b.right_line_from
-            } as usize;
+            let (from, to) = block_range(b, is_left);
+            let line_from = from.0;
 
             let opposite_line_from = if is_left {
                 b.right_line_from
@@ -136,19 +188,10 @@ pub async fn render_match_frame(
             } as usize;
             let opposite_side = if is_left { "right" } else { "left" };
 
-            let line_to = if is_left {
-                b.left_line_to
-            } else {
-                b.right_line_to
-            } as usize;
-
-            assert!(last_line <= line_from);
-            assert!(line_from <= line_to);
-            if last_line < line_from {
-                res += &html_escape::encode_text(&lines[last_line..=(line_from - 1)].join("\n"));
-                res += "\n";
-            }
-            last_line = line_to + 1;
+            assert!(last <= from);
+            assert!(from <= to);
+            res += &html_escape::encode_text(&slice_lines(&lines, last, from));
+            last = to;
 
             // add link to jump to opposite side
             res += &format!("<a name=\"{}\">", line_from);
@@ -158,20 +201,14 @@ pub async fn render_match_frame(
                 opposite_side, opposite_line_from, opposite_side
             );
             res += &gen_svg(colors[idx % 5], 1);
-            res += "</a>\n";
-            res += &format!(
-                "{}",
-                html_escape::encode_text(&lines[line_from..=line_to].join("\n"))
-            );
-            res += "\n";
+            res += "</a>";
+            // exactly the matched characters
+            res += &html_escape::encode_text(&slice_lines(&lines, from, to));
             res += "</font>";
         }
 
         // the rest
-        if last_line < lines.len() {
-            res += &html_escape::encode_text(&lines[last_line..].join("\n"));
-            res += "\n";
-        }
+        res += &html_escape::encode_text(&slice_lines(&lines, last, (lines.len(), 0)));
 
         res += "</pre></body></html>";
     }
@@ -230,7 +267,9 @@ pub async fn render_job(pool: web::Data<DbPool>, slug: web::Path<String>) -> Res
             .map_err(err)?;
         res += &format!(
             "<td><a href=\"./{}/\">{} ({}%)</a></td>",
-            idx, left_s.name, m.left_match_rate
+            idx,
+            display_name(&left_s),
+            m.left_match_rate
         );
         let right_s = crate::schema::submissions::dsl::submissions
             .filter(crate::schema::submissions::dsl::id.eq(m.right_submission_id))
@@ -238,7 +277,9 @@ pub async fn render_job(pool: web::Data<DbPool>, slug: web::Path<String>) -> Res
             .map_err(err)?;
         res += &format!(
             "<td><a href=\"./{}/\">{} ({}%)</a></td>",
-            idx, right_s.name, m.right_match_rate
+            idx,
+            display_name(&right_s),
+            m.right_match_rate
         );
         res += &format!("<td align=\"right\">{}</td>", m.lines_matched);
         res += "</tr>";
@@ -8,6 +8,10 @@ diesel::table! {
         left_line_to -> Int4,
         right_line_from -> Int4,
         right_line_to -> Int4,
+        left_column_from -> Nullable<Int4>,
+        left_column_to -> Nullable<Int4>,
+        right_column_from -> Nullable<Int4>,
+        right_column_to -> Nullable<Int4>,
     }
 }
 
@@ -37,6 +41,7 @@ diesel::table! {
         job_id -> Int4,
         name -> Text,
         code -> Text,
+        diagnostics -> Text,
     }
 }
 
@@ -41,10 +41,16 @@ async fn work(
         let new_submissions: Vec<NewSubmission> = req
             .submissions
             .iter()
-            .map(|s| NewSubmission {
+            .zip(work.diagnostics.iter())
+            .map(|(s, diagnostics)| NewSubmission {
                 job_id,
                 name: s.name.clone(),
                 code: s.code.clone(),
+                diagnostics: diagnostics
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
             })
             .collect();
         let submission_ids: Vec<i32> = diesel::insert_into(crate::schema::submissions::table)
@@ -81,6 +87,10 @@ async fn work(
                     left_line_to: b.left_line_to as i32,
                     right_line_from: b.right_line_from as i32,
                     right_line_to: b.right_line_to as i32,
+                    left_column_from: Some(b.left_column_from as i32),
+                    left_column_to: Some(b.left_column_to as i32),
+                    right_column_from: Some(b.right_column_from as i32),
+                    right_column_to: Some(b.right_column_to as i32),
                 })
                 .collect();
             diesel::insert_into(crate::schema::blocks::table)
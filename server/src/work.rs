@@ -3,6 +3,7 @@ use core::{
     common::{all_fingerprint, fingerprint, Fingerprint},
     lang::tokenize_str,
     matching::{compute_matching_blocks_from_text, Block},
+    token::Diagnostic,
 };
 
 use log::*;
@@ -11,6 +12,8 @@ use std::collections::HashMap;
 pub struct WorkResult {
     pub req: SubmitRequest,
     pub matches: Vec<Match>,
+    /// for each submission, empty if it was fully parsed
+    pub diagnostics: Vec<Vec<Diagnostic>>,
 }
 
 pub struct Match {
@@ -25,15 +28,25 @@ pub struct Match {
 pub fn work_blocking(req: SubmitRequest) -> anyhow::Result<WorkResult> {
     // tokenize template
     let template_tokens = if let Some(template) = &req.template {
-        tokenize_str(template, req.language)?
+        tokenize_str(template, req.language)?.tokens
     } else {
         vec![]
     };
 
     // tokenize sources
     let mut all_tokens = vec![];
+    let mut diagnostics = vec![];
     for submission in &req.submissions {
-        all_tokens.push(tokenize_str(&submission.code, req.language)?);
+        let tokenized = tokenize_str(&submission.code, req.language)?;
+        if !tokenized.diagnostics.is_empty() {
+            info!(
+                "{}: only partly parsed, {} diagnostics",
+                submission.name,
+                tokenized.diagnostics.len()
+            );
+        }
+        all_tokens.push(tokenized.tokens);
+        diagnostics.push(tokenized.diagnostics);
     }
     info!("Tokenized {} files in submission", all_tokens.len());
 
@@ -146,5 +159,9 @@ pub fn work_blocking(req: SubmitRequest) -> anyhow::Result<WorkResult> {
     matches.sort_by_key(|m| m.lines_matched);
     matches.reverse();
 
-    Ok(WorkResult { req, matches })
+    Ok(WorkResult {
+        req,
+        matches,
+        diagnostics,
+    })
 }